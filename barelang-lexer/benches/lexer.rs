@@ -0,0 +1,71 @@
+//! Measures the lexer on large synthetic sources, one per hot path.
+//!
+//! Each input is lexed in full, once reported in bytes per second and once in
+//! tokens per second.
+
+use std::hint::black_box;
+
+use barelang_lexer::Lexer;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+fn identifiers(tasks: usize) -> String {
+    (0..tasks)
+        .map(|i| {
+            format!(
+                "task build_{i}(target, profile) {{\n    run(compile_{i}, link_{i}, \"out/{i}\", 0x{i:x}, {i}.5)\n}}\n"
+            )
+        })
+        .collect()
+}
+
+fn whitespace(tasks: usize) -> String {
+    (0..tasks)
+        .map(|i| format!("task\n\n    t{i}    \t\t  {{ \r\n\n        }}      # done\n\n\n"))
+        .collect()
+}
+
+fn errors(tasks: usize) -> String {
+    (0..tasks)
+        .map(|i| format!("task e{i} {{ ? ~ ^ ?? `oops` \\ }}\n"))
+        .collect()
+}
+
+// a full pass over `input`, counting the tokens and errors
+fn lex(input: &str) -> usize {
+    Lexer::new(input)
+        .inspect(|token| _ = black_box(token))
+        .count()
+}
+
+fn bench_lexer(c: &mut Criterion) {
+    let inputs = [
+        ("identifiers", identifiers(10_000)),
+        ("whitespace", whitespace(10_000)),
+        ("errors", errors(10_000)),
+    ];
+
+    let mut bytes = c.benchmark_group("bytes");
+    for (name, input) in &inputs {
+        bytes.throughput(Throughput::Bytes(input.len() as u64));
+        bytes.bench_with_input(
+            BenchmarkId::from_parameter(name),
+            input.as_str(),
+            |b, input| b.iter(|| lex(black_box(input))),
+        );
+    }
+    bytes.finish();
+
+    let mut tokens = c.benchmark_group("tokens");
+    for (name, input) in &inputs {
+        tokens.throughput(Throughput::Elements(lex(input) as u64));
+        tokens.bench_with_input(
+            BenchmarkId::from_parameter(name),
+            input.as_str(),
+            |b, input| b.iter(|| lex(black_box(input))),
+        );
+    }
+    tokens.finish();
+}
+
+criterion_group!(benches, bench_lexer);
+criterion_main!(benches);
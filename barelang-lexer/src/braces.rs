@@ -0,0 +1,292 @@
+use alloc::vec::Vec;
+
+use crate::{
+    error::{
+        BraceDepthError, DelimiterMismatchError, LexError, UnclosedBraceError,
+        UnclosedDelimiterError, UnmatchedBraceError, UnmatchedDelimiterError,
+    },
+    Token, TokenKind,
+};
+
+/// Checks that every `{` in `tokens` is closed by a later `}`.
+///
+/// An unmatched `}` is reported where it is, and a `{` still open at the end
+/// of the tokens where it opened.
+pub fn check_braces(tokens: &[Token]) -> Result<(), LexError> {
+    check(tokens, None)
+}
+
+/// Like [`check_braces`], but also fails on the first `{` that nests deeper
+/// than `max_depth`, so a recursive parser cannot overflow its stack.
+pub fn check_brace_depth(tokens: &[Token], max_depth: usize) -> Result<(), LexError> {
+    check(tokens, Some(max_depth))
+}
+
+/// Like [`check_braces`] for parentheses and brackets too, which also fails
+/// on a delimiter closed by another kind, like the `)` in `{)` or in `([)]`.
+///
+/// Unmatched and unclosed delimiters of any kind are reported as
+/// [`LexError::UnmatchedDelimiter`] and [`LexError::UnclosedDelimiter`].
+pub fn check_delimiters(tokens: &[Token]) -> Result<(), LexError> {
+    let mut open = Vec::new();
+    for token in tokens {
+        match token.kind {
+            TokenKind::LeftBrace | TokenKind::LeftParen | TokenKind::LeftBracket => {
+                open.push(token);
+            }
+            TokenKind::RightBrace | TokenKind::RightParen | TokenKind::RightBracket => {
+                let Some(opener) = open.pop() else {
+                    return Err(UnmatchedDelimiterError {
+                        src: token.error_source(),
+                        delimiter: token.kind,
+                        err_span: token.span().into(),
+                    }
+                    .into());
+                };
+                let expected = match opener.kind {
+                    TokenKind::LeftBrace => TokenKind::RightBrace,
+                    TokenKind::LeftParen => TokenKind::RightParen,
+                    _ => TokenKind::RightBracket,
+                };
+                if token.kind != expected {
+                    return Err(DelimiterMismatchError {
+                        src: opener.error_source_through(token),
+                        expected,
+                        found: token.kind,
+                        opener_span: opener.span().into(),
+                        err_span: token.span().into(),
+                    }
+                    .into());
+                }
+            }
+            _ => {}
+        }
+    }
+    // the outermost unclosed delimiter is the one the final closer is missing for
+    match open.first() {
+        Some(opener) => Err(UnclosedDelimiterError {
+            src: opener.error_source(),
+            delimiter: opener.kind,
+            err_span: opener.span().into(),
+        }
+        .into()),
+        None => Ok(()),
+    }
+}
+
+fn check(tokens: &[Token], max_depth: Option<usize>) -> Result<(), LexError> {
+    let mut open = Vec::new();
+    for token in tokens {
+        match token.kind {
+            TokenKind::LeftBrace => {
+                if let Some(max_depth) = max_depth.filter(|&max| open.len() == max) {
+                    return Err(BraceDepthError {
+                        src: token.error_source(),
+                        max_depth,
+                        err_span: token.span().into(),
+                    }
+                    .into());
+                }
+                open.push(token);
+            }
+            TokenKind::RightBrace if open.pop().is_none() => {
+                return Err(UnmatchedBraceError {
+                    src: token.error_source(),
+                    err_span: token.span().into(),
+                }
+                .into());
+            }
+            _ => {}
+        }
+    }
+    unclosed(&open)
+}
+
+fn unclosed(open: &[&Token]) -> Result<(), LexError> {
+    // the outermost unclosed brace is the one the final `}` is missing for
+    match open.first() {
+        Some(opener) => Err(UnclosedBraceError {
+            src: opener.error_source(),
+            err_span: opener.span().into(),
+        }
+        .into()),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{error::LexError, tokenize, TokenKind};
+
+    use super::{check_brace_depth, check_braces, check_delimiters};
+
+    #[test]
+    fn it_accepts_nested_braces() {
+        assert!(check_braces(&tokenize("{{}}").unwrap()).is_ok());
+        assert!(check_braces(&tokenize("task a { b { } } task c {}").unwrap()).is_ok());
+        assert!(check_braces(&[]).is_ok());
+    }
+
+    #[test]
+    fn it_fails_on_an_unmatched_closing_brace() {
+        let res = check_braces(&tokenize("task a {}\n}").unwrap());
+        let Err(LexError::UnmatchedBrace(e)) = res else {
+            panic!("should have failed");
+        };
+        assert_eq!((10, 1), (e.err_span.offset(), e.err_span.len()));
+    }
+
+    #[test]
+    fn it_fails_on_an_unclosed_brace() {
+        let res = check_braces(&tokenize("task a { b {}").unwrap());
+        let Err(LexError::UnclosedBrace(e)) = res else {
+            panic!("should have failed");
+        };
+        assert_eq!((7, 1), (e.err_span.offset(), e.err_span.len()));
+
+        let res = check_braces(&tokenize("{").unwrap());
+        assert!(matches!(res, Err(LexError::UnclosedBrace(_))));
+    }
+
+    #[test]
+    fn it_accepts_braces_nested_up_to_the_max_depth() {
+        let tokens = tokenize("{ {} { {} } }").unwrap();
+        assert!(check_brace_depth(&tokens, 3).is_ok());
+        assert!(check_brace_depth(&tokenize("task a {}").unwrap(), 1).is_ok());
+        assert!(check_brace_depth(&tokenize("no braces").unwrap(), 0).is_ok());
+    }
+
+    #[test]
+    fn it_fails_on_a_brace_beyond_the_max_depth() {
+        let tokens = tokenize("{ {} { {} } }").unwrap();
+        let Err(LexError::NestedTooDeep(e)) = check_brace_depth(&tokens, 2) else {
+            panic!("should have failed");
+        };
+        assert_eq!(2, e.max_depth);
+        assert_eq!((7, 1), (e.err_span.offset(), e.err_span.len()));
+        assert_eq!("Braces are nested deeper than 2 levels", e.to_string());
+    }
+
+    #[test]
+    fn it_still_checks_the_balance_with_a_max_depth() {
+        let res = check_brace_depth(&tokenize("{ }}").unwrap(), 4);
+        assert!(matches!(res, Err(LexError::UnmatchedBrace(_))));
+    }
+
+    #[test]
+    fn it_accepts_nested_delimiters() {
+        assert!(check_delimiters(&tokenize("({[]})").unwrap()).is_ok());
+        assert!(check_delimiters(&tokenize("task a { b(c[0], []) }").unwrap()).is_ok());
+    }
+
+    #[test]
+    fn it_fails_on_a_delimiter_closed_by_another_kind() {
+        let res = check_delimiters(&tokenize("{)").unwrap());
+        let Err(LexError::MismatchedDelimiter(e)) = res else {
+            panic!("should have failed");
+        };
+        assert_eq!(
+            (TokenKind::RightBrace, TokenKind::RightParen),
+            (e.expected, e.found)
+        );
+        assert_eq!((0, 1), (e.opener_span.offset(), e.opener_span.len()));
+        assert_eq!((1, 1), (e.err_span.offset(), e.err_span.len()));
+        assert_eq!("Mismatched closing delimiter ), expected }", e.to_string());
+
+        let res = check_delimiters(&tokenize("([)]").unwrap());
+        let Err(LexError::MismatchedDelimiter(e)) = res else {
+            panic!("should have failed");
+        };
+        assert_eq!(
+            (TokenKind::RightBracket, TokenKind::RightParen),
+            (e.expected, e.found)
+        );
+        assert_eq!((1, 2), (e.opener_span.offset(), e.err_span.offset()));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn it_labels_both_delimiters_of_a_mismatch() {
+        let Err(e) = check_delimiters(&tokenize("{\n)").unwrap()) else {
+            panic!("should have failed");
+        };
+        let labels = miette::Diagnostic::labels(&e)
+            .unwrap()
+            .map(|label| label.offset())
+            .collect::<Vec<_>>();
+        assert_eq!(vec![0, 2], labels);
+    }
+
+    #[test]
+    fn it_fails_on_unbalanced_delimiters() {
+        let res = check_delimiters(&tokenize("a)").unwrap());
+        assert!(matches!(res, Err(LexError::UnmatchedDelimiter(_))));
+        let res = check_delimiters(&tokenize("[ ( )").unwrap());
+        let Err(LexError::UnclosedDelimiter(e)) = res else {
+            panic!("should have failed");
+        };
+        assert_eq!(0, e.err_span.offset());
+        assert_eq!(TokenKind::LeftBracket, e.delimiter);
+    }
+
+    #[test]
+    fn it_names_the_delimiter_that_is_unmatched_or_unclosed() {
+        let Err(e) = check_delimiters(&tokenize(")").unwrap()) else {
+            panic!("should have failed");
+        };
+        assert_eq!("Unmatched closing delimiter `)`", e.to_string());
+        let Err(e) = check_delimiters(&tokenize("(").unwrap()) else {
+            panic!("should have failed");
+        };
+        assert_eq!("Unclosed delimiter `(`", e.to_string());
+        let Err(e) = check_delimiters(&tokenize("{ ]").unwrap()) else {
+            panic!("should have failed");
+        };
+        assert!(matches!(e, LexError::MismatchedDelimiter(_)));
+        let Err(e) = check_delimiters(&tokenize("}").unwrap()) else {
+            panic!("should have failed");
+        };
+        assert_eq!("Unmatched closing delimiter `}`", e.to_string());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn it_labels_an_unclosed_delimiter() {
+        let Err(e) = check_delimiters(&tokenize("a [").unwrap()) else {
+            panic!("should have failed");
+        };
+        let labels = miette::Diagnostic::labels(&e)
+            .unwrap()
+            .map(|label| (label.offset(), label.label().map(str::to_string)))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![(2, Some("this delimiter is never closed".to_string()))],
+            labels
+        );
+    }
+
+    #[test]
+    fn it_only_copies_the_lines_around_the_error() {
+        let res = check_delimiters(&tokenize("a\nb (\nc\nd ]\ne\nf").unwrap());
+        let Err(LexError::MismatchedDelimiter(e)) = res else {
+            panic!("should have failed");
+        };
+        assert_eq!("a\nb (\nc\nd ]\ne\n", &*e.src);
+
+        let res = check_braces(&tokenize("a\nb\nc {\nd\ne").unwrap());
+        let Err(LexError::UnclosedBrace(e)) = res else {
+            panic!("should have failed");
+        };
+        assert_eq!("b\nc {\nd\n", &*e.src);
+        assert_eq!((2, 2), (e.src.first_line, e.src.base));
+
+        #[cfg(feature = "std")]
+        {
+            let mut report = String::new();
+            miette::GraphicalReportHandler::new_themed(miette::GraphicalTheme::unicode_nocolor())
+                .render_report(&mut report, &e)
+                .unwrap();
+            assert!(report.contains("[3:3]"), "{report}");
+        }
+    }
+}
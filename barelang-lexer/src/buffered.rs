@@ -0,0 +1,273 @@
+use std::io::{self, BufRead};
+
+use crate::{
+    error::{BufLexError, LexError},
+    is_whitespace, Lexer, OwnedToken, TokenKind,
+};
+
+/// Lexes a source read from `reader` without holding all of it in memory.
+///
+/// Only the text from the start of the current token up to the end of what was
+/// read so far is buffered, so tokens come out as [`OwnedToken`]s. Lex errors
+/// only have that buffered window as their source code, but their spans are
+/// still offsets into the whole source.
+///
+/// A token comes out once the whitespace after it is read, so a long line is
+/// not buffered whole. What is buffered at a time is bounded by the longest
+/// token, like a string or block comment spanning lines, or the longest run of
+/// the source without whitespace.
+pub struct BufLexer<R> {
+    reader: R,
+    buf: String,
+    // the part of `buf` that is already lexed
+    pos: usize,
+    // the offset of `buf` in the whole source
+    base: usize,
+    // the start of a char split by a chunk boundary
+    pending: Vec<u8>,
+    reader_done: bool,
+    finished: bool,
+    trivia: bool,
+}
+
+impl<R: BufRead> BufLexer<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            buf: String::new(),
+            pos: 0,
+            base: 0,
+            pending: Vec::new(),
+            reader_done: false,
+            finished: false,
+            trivia: false,
+        }
+    }
+
+    /// See [`Lexer::with_trivia`].
+    pub fn with_trivia(mut self) -> Self {
+        self.trivia = true;
+        self
+    }
+
+    // lexes the next token of the window, with the bytes of it that were used
+    fn lex_window(&self) -> (Result<OwnedToken, LexError>, usize) {
+        let start = self.base + self.pos;
+        let mut lexer = Lexer::new_at(&self.buf[self.pos..], start);
+        if self.trivia {
+            lexer = lexer.with_trivia();
+        }
+        let next = lexer
+            .next()
+            .expect("a fresh lexer yields at least its Eof")
+            .map(|token| token.to_owned_token());
+        (next, lexer.byte_offset() - start)
+    }
+
+    // a token may only change with more input unless whitespace follows it,
+    // since nothing in the lexer looks ahead past the next whitespace but for
+    // the `\n` of a `\r\n`
+    fn is_final(&self, used: usize) -> bool {
+        let after = &self.buf[self.pos + used..];
+        self.reader_done
+            || after
+                .find(is_whitespace)
+                .is_some_and(|i| i + 1 < after.len())
+    }
+
+    fn read_more(&mut self) -> io::Result<()> {
+        let chunk = loop {
+            match self.reader.fill_buf() {
+                Ok(chunk) => break chunk,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        };
+        if chunk.is_empty() {
+            self.reader_done = true;
+            if !self.pending.is_empty() {
+                return Err(invalid_utf8());
+            }
+            return Ok(());
+        }
+        self.pending.extend_from_slice(chunk);
+        let read = chunk.len();
+        self.reader.consume(read);
+
+        self.buf.drain(..self.pos);
+        self.base += self.pos;
+        self.pos = 0;
+
+        let valid = match std::str::from_utf8(&self.pending) {
+            Ok(_) => self.pending.len(),
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(_) => return Err(invalid_utf8()),
+        };
+        let text = std::str::from_utf8(&self.pending[..valid]).expect("checked above");
+        self.buf.push_str(text);
+        self.pending.drain(..valid);
+        Ok(())
+    }
+}
+
+fn invalid_utf8() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "stream did not contain valid UTF-8",
+    )
+}
+
+impl<R: BufRead> Iterator for BufLexer<R> {
+    type Item = Result<OwnedToken, BufLexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        loop {
+            let (next, used) = self.lex_window();
+            let is_eof = matches!(&next, Ok(token) if token.kind == TokenKind::Eof);
+            if self.reader_done || (!is_eof && self.is_final(used)) {
+                if is_eof {
+                    self.finished = true;
+                }
+                self.pos += used;
+                return Some(next.map_err(BufLexError::from));
+            }
+            if let Err(e) = self.read_more() {
+                self.finished = true;
+                return Some(Err(e.into()));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{BufReader, Cursor};
+
+    use quickcheck_macros::quickcheck;
+
+    use crate::{error::BufLexError, error::LexError, Lexer, OwnedToken};
+
+    use super::BufLexer;
+
+    const SOURCE: &str = "task build(target: \"dëbug\\n\") {\n    # héllo wörld\n    \
+        let x = 1_000.5e-3;\n    /* a /* nested */ comment */\n    run(r#\"raw \"text\"\"#, 'ü', 0xff)\n    \
+        script = <<EOF\n  echo hi\n  EOF\n}\n";
+
+    // the lookahead of numbers, raw strings and operators, all on one line
+    const ONE_LINE: &str = "a 1.5 2e+3 4e 5. r#\"x\"# -> -- != <= < <<EOF !x $HOME 'c' \"s t\" r##";
+
+    fn in_memory(lexer: Lexer) -> Vec<OwnedToken> {
+        lexer.map(|t| t.unwrap().to_owned_token()).collect()
+    }
+
+    fn chunked(capacity: usize) -> BufLexer<BufReader<Cursor<&'static str>>> {
+        BufLexer::new(BufReader::with_capacity(capacity, Cursor::new(SOURCE)))
+    }
+
+    #[test]
+    fn it_lexes_like_the_in_memory_lexer_across_chunk_boundaries() {
+        let expected = in_memory(Lexer::new(SOURCE));
+        for capacity in 1..=16 {
+            let tokens = chunked(capacity).map(|t| t.unwrap()).collect::<Vec<_>>();
+            assert_eq!(expected, tokens, "with chunks of {capacity} bytes");
+        }
+    }
+
+    #[test]
+    fn it_lexes_like_the_in_memory_lexer_within_a_line() {
+        let expected = in_memory(Lexer::new(ONE_LINE).with_trivia());
+        for capacity in 1..=16 {
+            let reader = BufReader::with_capacity(capacity, Cursor::new(ONE_LINE));
+            let tokens = BufLexer::new(reader)
+                .with_trivia()
+                .map(|t| t.unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(expected, tokens, "with chunks of {capacity} bytes");
+        }
+    }
+
+    #[quickcheck]
+    fn prop_lexes_like_the_in_memory_lexer(picks: Vec<u8>, capacity: u8) -> bool {
+        const PIECES: [&str; 24] = [
+            "a", "r", "1", "e", ".", "+", "#", "\"", "'", "<", "<<", "EOF", "-", ">", "=", "!",
+            "&", "|", "?", "$", "/", "*", " ", "\n",
+        ];
+        let input = picks
+            .iter()
+            .map(|&i| PIECES[i as usize % PIECES.len()])
+            .collect::<String>();
+        // errors only differ in their source code, so compare their messages
+        let expected = Lexer::new(&input)
+            .with_trivia()
+            .map(|t| t.map(|t| t.to_owned_token()).map_err(|e| e.to_string()))
+            .collect::<Vec<_>>();
+        let reader = BufReader::with_capacity(1 + capacity as usize % 8, Cursor::new(&input));
+        let tokens = BufLexer::new(reader)
+            .with_trivia()
+            .map(|t| t.map_err(|e| e.to_string()))
+            .collect::<Vec<_>>();
+        expected == tokens
+    }
+
+    #[test]
+    fn it_does_not_buffer_a_long_line_whole() {
+        let line = "foo(bar, 42) ".repeat(10_000);
+        let reader = BufReader::with_capacity(16, Cursor::new(line.as_bytes()));
+        let mut lexer = BufLexer::new(reader);
+        let mut tokens = 0;
+        while let Some(token) = lexer.next() {
+            token.unwrap();
+            tokens += 1;
+            assert!(lexer.buf.len() <= 32, "buffered {} bytes", lexer.buf.len());
+        }
+        assert_eq!(6 * 10_000 + 1, tokens);
+    }
+
+    #[test]
+    fn it_keeps_trivia_across_chunk_boundaries() {
+        let expected = in_memory(Lexer::new(SOURCE).with_trivia());
+        for capacity in 1..=16 {
+            let tokens = chunked(capacity)
+                .with_trivia()
+                .map(|t| t.unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(expected, tokens, "with chunks of {capacity} bytes");
+        }
+    }
+
+    #[test]
+    fn it_reports_lex_errors_and_keeps_going() {
+        let reader = BufReader::with_capacity(2, Cursor::new("foo ? bar"));
+        let tokens = BufLexer::new(reader).collect::<Vec<_>>();
+        assert_eq!(4, tokens.len());
+        let Err(BufLexError::Lex(LexError::UnexpectedChar(e))) = &tokens[1] else {
+            panic!("should have failed");
+        };
+        assert_eq!('?', e.token);
+        assert_eq!(4, e.err_span.offset());
+        assert_eq!("bar", tokens[2].as_ref().unwrap().text);
+        assert_eq!(6, tokens[2].as_ref().unwrap().offset);
+    }
+
+    #[test]
+    fn it_fails_on_invalid_utf8() {
+        let reader = BufReader::with_capacity(2, Cursor::new(b"foo\n\nbar \xff".to_vec()));
+        let mut lexer = BufLexer::new(reader);
+        assert_eq!("foo", lexer.next().unwrap().unwrap().text);
+        let Some(Err(BufLexError::Io(e))) = lexer.next() else {
+            panic!("should have failed");
+        };
+        assert_eq!(std::io::ErrorKind::InvalidData, e.kind());
+        assert!(lexer.next().is_none());
+    }
+
+    #[test]
+    fn it_fails_on_a_char_cut_off_by_the_end_of_input() {
+        let reader = BufReader::with_capacity(2, Cursor::new(b"foo\n\nbar\xc3".to_vec()));
+        let results = BufLexer::new(reader).collect::<Vec<_>>();
+        assert!(matches!(&results[..], [Ok(_), Err(BufLexError::Io(_))]));
+    }
+}
@@ -0,0 +1,777 @@
+use alloc::boxed::Box;
+use core::cell::Cell;
+
+use crate::{error::LexError, Lexer, TokenKind, DEFAULT_KEYWORDS};
+
+pub struct LexerBuilder<'k> {
+    keywords: &'k [(&'k str, TokenKind)],
+    trivia: bool,
+    tab_width: usize,
+    dotted_paths: bool,
+    significant_newlines: bool,
+    max_ident_len: Option<usize>,
+    raw_identifiers: bool,
+    unit_suffixes: bool,
+    allow_leading_digit_idents: bool,
+    line_continuations: bool,
+    unknown_tokens: bool,
+    max_tokens: Option<usize>,
+    max_bytes: Option<usize>,
+    on_error: Option<Box<dyn FnMut(LexError) + Send + 'k>>,
+}
+
+impl LexerBuilder<'static> {
+    pub fn new() -> Self {
+        Self {
+            keywords: DEFAULT_KEYWORDS,
+            trivia: false,
+            tab_width: 1,
+            dotted_paths: false,
+            significant_newlines: false,
+            max_ident_len: None,
+            raw_identifiers: false,
+            unit_suffixes: false,
+            allow_leading_digit_idents: false,
+            line_continuations: false,
+            unknown_tokens: false,
+            max_tokens: None,
+            max_bytes: None,
+            on_error: None,
+        }
+    }
+}
+
+impl Default for LexerBuilder<'static> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'k> LexerBuilder<'k> {
+    /// Replaces the [`DEFAULT_KEYWORDS`]; every other word lexes as a
+    /// [`TokenKind::Ident`].
+    pub fn keywords<'a>(self, keywords: &'a [(&'a str, TokenKind)]) -> LexerBuilder<'a>
+    where
+        'k: 'a,
+    {
+        LexerBuilder {
+            keywords,
+            on_error: self.on_error,
+            ..self
+        }
+    }
+
+    /// Lexes every word as a [`TokenKind::Ident`], keywords included, like
+    /// [`LexerBuilder::keywords`] with none would.
+    pub fn raw_identifiers(mut self, raw_identifiers: bool) -> Self {
+        self.raw_identifiers = raw_identifiers;
+        self
+    }
+
+    /// See [`Lexer::with_trivia`].
+    pub fn trivia(mut self, trivia: bool) -> Self {
+        self.trivia = trivia;
+        self
+    }
+
+    /// How many columns apart the tab stops are for [`Token::column`](crate::Token::column) of the
+    /// lexed tokens. Defaults to 1.
+    ///
+    /// # Panics
+    ///
+    /// If `tab_width` is 0.
+    pub fn tab_width(mut self, tab_width: usize) -> Self {
+        assert!(tab_width > 0, "tab stops must be at least one column apart");
+        self.tab_width = tab_width;
+        self
+    }
+
+    /// Lexes identifiers joined by dots, like `a.b.c`, as one
+    /// [`TokenKind::Path`] token instead of identifiers and [`TokenKind::Dot`]s.
+    /// A path is never a keyword, so `task.name` is a path too.
+    pub fn dotted_paths(mut self, dotted_paths: bool) -> Self {
+        self.dotted_paths = dotted_paths;
+        self
+    }
+
+    /// Lexes line breaks, `\n` or `\r\n`, as [`TokenKind::Newline`] tokens
+    /// for grammars that end statements with them, instead of skipping them
+    /// like other whitespace.
+    pub fn significant_newlines(mut self, significant_newlines: bool) -> Self {
+        self.significant_newlines = significant_newlines;
+        self
+    }
+
+    /// Fails on identifiers, keywords and dotted paths longer than `max_len`
+    /// chars, to put a bound on pathological input. There is no limit by
+    /// default.
+    ///
+    /// The error covers the whole identifier and lexing goes on after it.
+    pub fn max_ident_len(mut self, max_len: usize) -> Self {
+        self.max_ident_len = Some(max_len);
+        self
+    }
+
+    /// Lexes an integer directly followed by a unit as one
+    /// [`TokenKind::Duration`], like `30s`, or [`TokenKind::ByteSize`], like
+    /// `10MB`, instead of an integer followed by an identifier.
+    pub fn unit_suffixes(mut self, unit_suffixes: bool) -> Self {
+        self.unit_suffixes = unit_suffixes;
+        self
+    }
+
+    /// Lexes an integer directly followed by letters, like the label `123abc`,
+    /// as one [`TokenKind::Ident`] instead of an integer followed by an
+    /// identifier. That includes words that only start like a prefixed
+    /// integer, like `0xyz` or `0b1x`, while `0x2a` stays an integer. A unit
+    /// of [`LexerBuilder::unit_suffixes`] still makes a
+    /// [`TokenKind::Duration`] or [`TokenKind::ByteSize`].
+    pub fn allow_leading_digit_idents(mut self, allow_leading_digit_idents: bool) -> Self {
+        self.allow_leading_digit_idents = allow_leading_digit_idents;
+        self
+    }
+
+    /// Skips a `\` right before a line break like whitespace, so a value can
+    /// go on over several lines. The `\` and the line break are a
+    /// [`TokenKind::Whitespace`] with trivia, and never a
+    /// [`TokenKind::Newline`].
+    pub fn line_continuations(mut self, line_continuations: bool) -> Self {
+        self.line_continuations = line_continuations;
+        self
+    }
+
+    /// Lexes a char that begins no token, or a run of such punctuation, as a
+    /// [`TokenKind::Unknown`] instead of failing on it, so that an editor
+    /// gets a token for every part of a broken source.
+    ///
+    /// Other errors, like an unterminated string, are still errors.
+    pub fn unknown_tokens(mut self, unknown_tokens: bool) -> Self {
+        self.unknown_tokens = unknown_tokens;
+        self
+    }
+
+    /// Fails on the token after the first `max_tokens` ones, not counting the
+    /// [`TokenKind::Eof`], and stops the lexing there, to bound the work on
+    /// untrusted input.
+    pub fn max_tokens(mut self, max_tokens: usize) -> Self {
+        self.max_tokens = Some(max_tokens);
+        self
+    }
+
+    /// Fails right away on an input longer than `max_bytes` and lexes none of
+    /// it, to bound the work on untrusted input.
+    pub fn max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Hands every error to `on_error` and goes on lexing, so the lexer only
+    /// yields tokens.
+    ///
+    /// An error that stops the lexing, like one for
+    /// [`LexerBuilder::max_tokens`], still ends it right after `on_error`.
+    pub fn on_error(mut self, on_error: impl FnMut(LexError) + Send + 'k) -> Self {
+        self.on_error = Some(Box::new(on_error));
+        self
+    }
+
+    pub fn build<'de>(self, input: &'de str) -> Lexer<'de>
+    where
+        'k: 'de,
+    {
+        let mut lexer = Lexer {
+            whole: input,
+            rest: input,
+            base: 0,
+            byte: 0,
+            reached_eof: false,
+            shared_whole: None,
+            shared_lines: None,
+            line_at: Cell::new((0, 1)),
+            keywords: if self.raw_identifiers {
+                &[]
+            } else {
+                self.keywords
+            },
+            trivia: self.trivia,
+            tab_width: self.tab_width,
+            dotted_paths: self.dotted_paths,
+            significant_newlines: self.significant_newlines,
+            max_ident_len: self.max_ident_len,
+            unit_suffixes: self.unit_suffixes,
+            allow_leading_digit_idents: self.allow_leading_digit_idents,
+            line_continuations: self.line_continuations,
+            unknown_tokens: self.unknown_tokens,
+            max_tokens: self.max_tokens,
+            max_bytes: self.max_bytes,
+            tokens_lexed: 0,
+            on_error: self.on_error,
+        };
+        lexer.reset();
+        lexer
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{error::LexError, Lexer, TokenKind};
+
+    use super::LexerBuilder;
+
+    fn kinds(lexer: Lexer) -> Vec<TokenKind> {
+        lexer.map(|t| t.unwrap().kind).collect()
+    }
+
+    #[test]
+    fn it_uses_the_default_keywords() {
+        let lexer = LexerBuilder::new().build("task if foo");
+        assert_eq!(
+            vec![
+                TokenKind::Task,
+                TokenKind::If,
+                TokenKind::Ident,
+                TokenKind::Eof
+            ],
+            kinds(lexer)
+        );
+    }
+
+    #[test]
+    fn it_uses_custom_keywords() {
+        let keywords = [("plugin", TokenKind::Task), ("when", TokenKind::If)];
+        let lexer = LexerBuilder::new()
+            .keywords(&keywords)
+            .build("plugin foo { when task {} }");
+        assert_eq!(
+            vec![
+                TokenKind::Task,
+                TokenKind::Ident,
+                TokenKind::LeftBrace,
+                TokenKind::If,
+                TokenKind::Ident,
+                TokenKind::LeftBrace,
+                TokenKind::RightBrace,
+                TokenKind::RightBrace,
+                TokenKind::Eof
+            ],
+            kinds(lexer)
+        );
+    }
+
+    #[test]
+    fn it_counts_tabs_as_configured_columns() {
+        for tab_width in [1, 4, 8] {
+            let mut lexer = LexerBuilder::new().tab_width(tab_width).build("\tfoo");
+            assert_eq!(tab_width, lexer.tab_width());
+            let foo = lexer.next().unwrap().unwrap();
+            assert_eq!(tab_width + 1, foo.column());
+            assert_eq!(tab_width + 1, foo.column_with_tab_width(lexer.tab_width()));
+        }
+        assert_eq!(1, Lexer::new("").tab_width());
+    }
+
+    #[test]
+    fn it_moves_tabs_on_to_the_next_tab_stop() {
+        for (tab_width, columns) in [(1, [1, 3, 7]), (4, [1, 5, 13]), (8, [1, 9, 25])] {
+            let tokens = LexerBuilder::new()
+                .tab_width(tab_width)
+                .build("a\tbc\t\td")
+                .map(|t| t.unwrap().column())
+                .take(3)
+                .collect::<Vec<_>>();
+            assert_eq!(columns.to_vec(), tokens, "with a tab width of {tab_width}");
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "tab stops must be at least one column apart")]
+    fn it_rejects_a_tab_width_of_zero() {
+        LexerBuilder::new().tab_width(0);
+    }
+
+    #[test]
+    fn it_lexes_dotted_paths_as_one_token_when_enabled() {
+        let lexer = LexerBuilder::new().dotted_paths(true).build("a.b.c task.λ");
+        let tokens = lexer.map(|t| t.unwrap()).collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                ("a.b.c", TokenKind::Path),
+                ("task.λ", TokenKind::Path),
+                ("", TokenKind::Eof)
+            ],
+            tokens
+                .iter()
+                .map(|t| (t.origin, t.kind))
+                .collect::<Vec<_>>()
+        );
+
+        let lexer = LexerBuilder::new()
+            .dotted_paths(true)
+            .build("foo. a.b. c..d");
+        assert_eq!(
+            vec![
+                TokenKind::Ident,
+                TokenKind::Dot,
+                TokenKind::Path,
+                TokenKind::Dot,
+                TokenKind::Ident,
+                TokenKind::Dot,
+                TokenKind::Dot,
+                TokenKind::Ident,
+                TokenKind::Eof
+            ],
+            kinds(lexer)
+        );
+    }
+
+    #[test]
+    fn it_lexes_dots_apart_by_default() {
+        assert_eq!(
+            vec![
+                TokenKind::Ident,
+                TokenKind::Dot,
+                TokenKind::Ident,
+                TokenKind::Dot,
+                TokenKind::Ident,
+                TokenKind::Eof
+            ],
+            kinds(Lexer::new("a.b.c"))
+        );
+        assert_eq!(
+            vec![TokenKind::Ident, TokenKind::Dot, TokenKind::Eof],
+            kinds(Lexer::new("foo."))
+        );
+    }
+
+    #[test]
+    fn it_lexes_newlines_when_significant() {
+        let lexer = LexerBuilder::new()
+            .significant_newlines(true)
+            .build("a\n\nb");
+        let tokens = lexer.map(|t| t.unwrap()).collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                ("a", TokenKind::Ident),
+                ("\n\n", TokenKind::Newline),
+                ("b", TokenKind::Ident),
+                ("", TokenKind::Eof)
+            ],
+            tokens
+                .iter()
+                .map(|t| (t.origin, t.kind))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![TokenKind::Ident, TokenKind::Ident, TokenKind::Eof],
+            kinds(Lexer::new("a\n\nb"))
+        );
+    }
+
+    #[test]
+    fn it_keeps_other_whitespace_apart_from_significant_newlines() {
+        let lexer = LexerBuilder::new()
+            .significant_newlines(true)
+            .trivia(true)
+            .build("a \r\n\t\r\n  b\r");
+        let tokens = lexer.map(|t| t.unwrap()).collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                ("a", TokenKind::Ident),
+                (" ", TokenKind::Whitespace),
+                ("\r\n\t\r\n", TokenKind::Newline),
+                ("  ", TokenKind::Whitespace),
+                ("b", TokenKind::Ident),
+                ("\r", TokenKind::Whitespace),
+                ("", TokenKind::Eof)
+            ],
+            tokens
+                .iter()
+                .map(|t| (t.origin, t.kind))
+                .collect::<Vec<_>>()
+        );
+
+        let lexer = LexerBuilder::new()
+            .significant_newlines(true)
+            .build("a # comment\n  b");
+        assert_eq!(
+            vec![
+                TokenKind::Ident,
+                TokenKind::Newline,
+                TokenKind::Ident,
+                TokenKind::Eof
+            ],
+            kinds(lexer)
+        );
+    }
+
+    #[test]
+    fn it_limits_the_length_of_identifiers() {
+        let mut lexer = LexerBuilder::new()
+            .max_ident_len(8)
+            .build("short verylongidentifier x");
+        let (tokens, errors) = lexer.lex_all();
+        assert_eq!(
+            vec!["short", "x", ""],
+            tokens.iter().map(|t| t.origin).collect::<Vec<_>>()
+        );
+        let [LexError::IdentTooLong(e)] = &errors[..] else {
+            panic!("should have failed once");
+        };
+        assert_eq!(8, e.max_len);
+        assert_eq!((6, 18), (e.err_span.offset(), e.err_span.len()));
+
+        let lexer = LexerBuilder::new().max_ident_len(8).build("abcdefgh");
+        assert_eq!(vec![TokenKind::Ident, TokenKind::Eof], kinds(lexer));
+        let lexer = LexerBuilder::new().max_ident_len(4).build("éééé");
+        assert_eq!(vec![TokenKind::Ident, TokenKind::Eof], kinds(lexer));
+        let mut lexer = LexerBuilder::new().max_ident_len(4).build("ééééé");
+        assert!(matches!(lexer.next(), Some(Err(LexError::IdentTooLong(_)))));
+        assert_eq!(
+            vec![TokenKind::Ident, TokenKind::Eof],
+            kinds(Lexer::new("verylongidentifier"))
+        );
+    }
+
+    #[test]
+    fn it_lexes_keywords_as_identifiers_in_raw_mode() {
+        let lexer = LexerBuilder::new()
+            .raw_identifiers(true)
+            .build("task foo {}");
+        assert_eq!(
+            vec![
+                TokenKind::Ident,
+                TokenKind::Ident,
+                TokenKind::LeftBrace,
+                TokenKind::RightBrace,
+                TokenKind::Eof
+            ],
+            kinds(lexer)
+        );
+
+        let keywords = [("plugin", TokenKind::Task)];
+        let lexer = LexerBuilder::new()
+            .keywords(&keywords)
+            .raw_identifiers(true)
+            .build("plugin");
+        assert_eq!(vec![TokenKind::Ident, TokenKind::Eof], kinds(lexer));
+        let lexer = LexerBuilder::new().raw_identifiers(false).build("task");
+        assert_eq!(vec![TokenKind::Task, TokenKind::Eof], kinds(lexer));
+    }
+
+    #[test]
+    fn it_lexes_unit_suffixes_when_enabled() {
+        let lexer = LexerBuilder::new()
+            .unit_suffixes(true)
+            .build("30s 10MB 5ms 2GiB 30sec 1.5s 0x10B");
+        let tokens = lexer.map(|t| t.unwrap()).collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                ("30s", TokenKind::Duration),
+                ("10MB", TokenKind::ByteSize),
+                ("5ms", TokenKind::Duration),
+                ("2GiB", TokenKind::ByteSize),
+                ("30", TokenKind::Integer),
+                ("sec", TokenKind::Ident),
+                ("1.5", TokenKind::Float),
+                ("s", TokenKind::Ident),
+                ("0x10B", TokenKind::Integer),
+                ("", TokenKind::Eof)
+            ],
+            tokens
+                .iter()
+                .map(|t| (t.origin, t.kind))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn it_lexes_unit_suffixes_apart_by_default() {
+        assert_eq!(
+            vec![
+                TokenKind::Integer,
+                TokenKind::Ident,
+                TokenKind::Integer,
+                TokenKind::Ident,
+                TokenKind::Eof
+            ],
+            kinds(Lexer::new("30s 10MB"))
+        );
+    }
+
+    #[test]
+    fn it_lexes_digit_led_identifiers_when_allowed() {
+        let lexer = LexerBuilder::new()
+            .allow_leading_digit_idents(true)
+            .build("123abc 1_2x 42 1.5x 0x2ag 0x2a");
+        let tokens = lexer.map(|t| t.unwrap()).collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                ("123abc", TokenKind::Ident),
+                ("1_2x", TokenKind::Ident),
+                ("42", TokenKind::Integer),
+                ("1.5", TokenKind::Float),
+                ("x", TokenKind::Ident),
+                ("0x2ag", TokenKind::Ident),
+                ("0x2a", TokenKind::Integer),
+                ("", TokenKind::Eof)
+            ],
+            tokens
+                .iter()
+                .map(|t| (t.origin, t.kind))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn it_lexes_words_starting_like_prefixed_integers_as_identifiers_when_allowed() {
+        let lexer = LexerBuilder::new()
+            .allow_leading_digit_idents(true)
+            .build("0bar 0xyz 0office 0b1x 0x 0b1_0 0o17");
+        let tokens = lexer.map(|t| t.unwrap()).collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                ("0bar", TokenKind::Ident),
+                ("0xyz", TokenKind::Ident),
+                ("0office", TokenKind::Ident),
+                ("0b1x", TokenKind::Ident),
+                ("0x", TokenKind::Ident),
+                ("0b1_0", TokenKind::Integer),
+                ("0o17", TokenKind::Integer),
+                ("", TokenKind::Eof)
+            ],
+            tokens
+                .iter()
+                .map(|t| (t.origin, t.kind))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn it_lexes_digit_led_identifiers_apart_by_default() {
+        let tokens = Lexer::new("123abc").map(|t| t.unwrap()).collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                ("123", TokenKind::Integer),
+                ("abc", TokenKind::Ident),
+                ("", TokenKind::Eof)
+            ],
+            tokens
+                .iter()
+                .map(|t| (t.origin, t.kind))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn it_prefers_unit_suffixes_over_digit_led_identifiers() {
+        let lexer = LexerBuilder::new()
+            .allow_leading_digit_idents(true)
+            .unit_suffixes(true)
+            .build("30s 30sec");
+        assert_eq!(
+            vec![TokenKind::Duration, TokenKind::Ident, TokenKind::Eof],
+            kinds(lexer)
+        );
+    }
+
+    #[test]
+    fn it_limits_the_length_of_digit_led_identifiers() {
+        let mut lexer = LexerBuilder::new()
+            .allow_leading_digit_idents(true)
+            .max_ident_len(4)
+            .build("1234abcd 12ab");
+        let Some(Err(LexError::IdentTooLong(e))) = lexer.next() else {
+            panic!("should have been IdentTooLong");
+        };
+        assert_eq!((0, 8), (e.err_span.offset(), e.err_span.len()));
+        assert_eq!("12ab", lexer.next().unwrap().unwrap().origin);
+    }
+
+    #[test]
+    fn it_lexes_unexpected_chars_as_unknown_tokens_when_enabled() {
+        let lexer = LexerBuilder::new()
+            .unknown_tokens(true)
+            .build("task ?foo {} a ?? → \"b");
+        let tokens = lexer.collect::<Vec<_>>();
+        let (tokens, errors): (Vec<_>, Vec<_>) = tokens.into_iter().partition(Result::is_ok);
+        assert_eq!(
+            vec![
+                (TokenKind::Task, "task"),
+                (TokenKind::Unknown, "?"),
+                (TokenKind::Ident, "foo"),
+                (TokenKind::LeftBrace, "{"),
+                (TokenKind::RightBrace, "}"),
+                (TokenKind::Ident, "a"),
+                (TokenKind::Unknown, "??"),
+                (TokenKind::Unknown, "→"),
+                (TokenKind::Eof, ""),
+            ],
+            tokens
+                .into_iter()
+                .map(|t| t.unwrap())
+                .map(|t| (t.kind, t.origin))
+                .collect::<Vec<_>>()
+        );
+        let [Err(LexError::UnterminatedString(_))] = &errors[..] else {
+            panic!("should only have failed on the unterminated string");
+        };
+    }
+
+    #[test]
+    fn it_stops_a_run_of_unknown_tokens_at_operators() {
+        let tokens = LexerBuilder::new()
+            .unknown_tokens(true)
+            .build("?<= a")
+            .map(|t| t.map(|t| (t.kind, t.origin)))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            vec![
+                (TokenKind::Unknown, "?"),
+                (TokenKind::LessEqual, "<="),
+                (TokenKind::Ident, "a"),
+                (TokenKind::Eof, "")
+            ],
+            tokens
+        );
+    }
+
+    #[test]
+    fn it_fails_on_unexpected_chars_by_default() {
+        let mut lexer = LexerBuilder::new().build("task ?foo");
+        assert_eq!(TokenKind::Task, lexer.next().unwrap().unwrap().kind);
+        assert!(lexer.next().unwrap().is_err());
+    }
+
+    #[test]
+    fn it_skips_line_continuations_when_enabled() {
+        let lexer = LexerBuilder::new()
+            .line_continuations(true)
+            .build("foo \\\n bar \\\r\nbaz");
+        assert_eq!(
+            vec![
+                TokenKind::Ident,
+                TokenKind::Ident,
+                TokenKind::Ident,
+                TokenKind::Eof
+            ],
+            kinds(lexer)
+        );
+
+        let lexer = LexerBuilder::new()
+            .line_continuations(true)
+            .significant_newlines(true)
+            .trivia(true)
+            .build("foo \\\nbar\n");
+        let tokens = lexer.map(|t| t.unwrap()).collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                ("foo", TokenKind::Ident),
+                (" ", TokenKind::Whitespace),
+                ("\\\n", TokenKind::Whitespace),
+                ("bar", TokenKind::Ident),
+                ("\n", TokenKind::Newline),
+                ("", TokenKind::Eof)
+            ],
+            tokens
+                .iter()
+                .map(|t| (t.origin, t.kind))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn it_fails_on_a_backslash_before_a_line_break_by_default() {
+        let mut lexer = Lexer::new("foo \\\n bar");
+        let (tokens, errors) = lexer.lex_all();
+        assert_eq!(3, tokens.len());
+        let [LexError::UnexpectedChar(e)] = &errors[..] else {
+            panic!("should have failed once");
+        };
+        assert_eq!(('\\', 4), (e.token, e.err_span.offset()));
+
+        let mut lexer = LexerBuilder::new()
+            .line_continuations(true)
+            .build("foo \\ bar");
+        assert_eq!(1, lexer.lex_all().1.len());
+    }
+
+    #[test]
+    fn it_lexes_up_to_the_max_tokens() {
+        let lexer = LexerBuilder::new().max_tokens(4).build("task foo {}");
+        assert_eq!(5, kinds(lexer).len());
+
+        let mut lexer = LexerBuilder::new().max_tokens(3).build("task foo {}");
+        let (tokens, errors) = lexer.lex_all();
+        assert_eq!(3, tokens.len());
+        let [LexError::TooManyTokens(e)] = &errors[..] else {
+            panic!("should have failed once");
+        };
+        assert_eq!(3, e.max_tokens);
+        assert_eq!((10, 1), (e.err_span.offset(), e.err_span.len()));
+        assert!(lexer.next().is_none());
+
+        lexer.reset();
+        assert_eq!(3, lexer.lex_all().0.len());
+    }
+
+    #[test]
+    fn it_lexes_inputs_up_to_the_max_bytes() {
+        let lexer = LexerBuilder::new().max_bytes(11).build("task foo {}");
+        assert_eq!(5, kinds(lexer).len());
+
+        let mut lexer = LexerBuilder::new().max_bytes(10).build("task foo {}");
+        let Some(Err(LexError::InputTooLarge(e))) = lexer.next() else {
+            panic!("should have failed");
+        };
+        assert_eq!(10, e.max_bytes);
+        assert_eq!((10, 1), (e.err_span.offset(), e.err_span.len()));
+        assert!(lexer.next().is_none());
+
+        // the span starts on a char boundary
+        let mut lexer = LexerBuilder::new().max_bytes(2).build("é🦀");
+        let Some(Err(LexError::InputTooLarge(e))) = lexer.next() else {
+            panic!("should have failed");
+        };
+        assert_eq!((2, 4), (e.err_span.offset(), e.err_span.len()));
+    }
+
+    #[test]
+    fn it_hands_errors_to_the_callback_and_goes_on() {
+        let mut errors = 0;
+        let lexer = LexerBuilder::new()
+            .on_error(|_| errors += 1)
+            .build("?foo$ bar");
+        assert_eq!(
+            vec![TokenKind::Ident, TokenKind::Ident, TokenKind::Eof],
+            kinds(lexer)
+        );
+        assert_eq!(2, errors);
+
+        let mut spans = Vec::new();
+        let keywords = [("plugin", TokenKind::Task)];
+        let lexer = LexerBuilder::new()
+            .on_error(|e| spans.push(e.span().start))
+            .keywords(&keywords)
+            .build("plugin ? x");
+        assert_eq!(
+            vec![TokenKind::Task, TokenKind::Ident, TokenKind::Eof],
+            kinds(lexer)
+        );
+        assert_eq!(vec![7], spans);
+    }
+
+    #[test]
+    fn it_enables_trivia() {
+        let lexer = LexerBuilder::new().trivia(true).build("# hi\nfoo");
+        assert_eq!(
+            vec![
+                TokenKind::Comment,
+                TokenKind::Whitespace,
+                TokenKind::Ident,
+                TokenKind::Eof
+            ],
+            kinds(lexer)
+        );
+    }
+}
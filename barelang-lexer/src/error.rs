@@ -1,25 +1,503 @@
-use miette::{Diagnostic, SourceSpan};
+use alloc::{string::String, sync::Arc};
+use core::{fmt, ops::Deref};
+
+#[cfg(feature = "std")]
+use miette::Diagnostic;
 use thiserror::Error;
 
+use crate::{line_index::line_of, span::SourceSpan, Span, TokenKind};
+
+/// The text an error points into: the lexed input, which starts `base` bytes
+/// into the whole source for a [`crate::Lexer::new_at`], or for an error about
+/// a token only the lines around it.
+#[derive(Clone)]
+pub(crate) struct ErrorSource {
+    pub(crate) text: Arc<str>,
+    pub(crate) base: usize,
+    // the 1-based line of the lexed input that `text` starts on
+    pub(crate) first_line: usize,
+    // the starts of the lines of `text`, as in a `LineIndex`
+    pub(crate) lines: Arc<[usize]>,
+}
+
+// the line starts are left out, a long source has too many of them to read
+impl fmt::Debug for ErrorSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ErrorSource")
+            .field("text", &self.text)
+            .field("base", &self.base)
+            .field("first_line", &self.first_line)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Deref for ErrorSource {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.text
+    }
+}
+
+// spans are absolute, so they are moved into `text` to read it and back out
+#[cfg(feature = "std")]
+impl miette::SourceCode for ErrorSource {
+    fn read_span<'a>(
+        &'a self,
+        span: &SourceSpan,
+        context_lines_before: usize,
+        context_lines_after: usize,
+    ) -> Result<Box<dyn miette::SpanContents<'a> + 'a>, miette::MietteError> {
+        let offset = span
+            .offset()
+            .checked_sub(self.base)
+            .ok_or(miette::MietteError::OutOfBounds)?;
+        let contents = (*self.text).read_span(
+            &SourceSpan::from(offset..offset + span.len()),
+            context_lines_before,
+            context_lines_after,
+        )?;
+        let contents_at = self.base + contents.span().offset();
+        Ok(Box::new(miette::MietteSpanContents::new(
+            contents.data(),
+            SourceSpan::from(contents_at..contents_at + contents.span().len()),
+            contents.line() + self.first_line - 1,
+            contents.column(),
+            contents.line_count(),
+        )))
+    }
+}
+
+#[derive(Debug, Error)]
+#[cfg_attr(feature = "std", derive(Diagnostic))]
+pub enum LexError {
+    #[error(transparent)]
+    #[cfg_attr(feature = "std", diagnostic(transparent))]
+    UnexpectedChar(#[from] SingleTokenError),
+
+    #[error(transparent)]
+    #[cfg_attr(feature = "std", diagnostic(transparent))]
+    UnexpectedSequence(#[from] UnexpectedSequenceError),
+
+    #[error(transparent)]
+    #[cfg_attr(feature = "std", diagnostic(transparent))]
+    UnterminatedString(#[from] StringTerminationError),
+
+    #[error(transparent)]
+    #[cfg_attr(feature = "std", diagnostic(transparent))]
+    InvalidChar(#[from] CharLiteralError),
+
+    #[error(transparent)]
+    #[cfg_attr(feature = "std", diagnostic(transparent))]
+    InvalidEscape(#[from] InvalidEscapeError),
+
+    #[error(transparent)]
+    #[cfg_attr(feature = "std", diagnostic(transparent))]
+    MissingDigits(#[from] MissingDigitsError),
+
+    #[error(transparent)]
+    #[cfg_attr(feature = "std", diagnostic(transparent))]
+    MisplacedDigitSeparator(#[from] DigitSeparatorError),
+
+    #[error(transparent)]
+    #[cfg_attr(feature = "std", diagnostic(transparent))]
+    UnterminatedBlockComment(#[from] BlockCommentTerminationError),
+
+    #[error(transparent)]
+    #[cfg_attr(feature = "std", diagnostic(transparent))]
+    InvalidUtf8(#[from] Utf8Error),
+
+    #[error(transparent)]
+    #[cfg_attr(feature = "std", diagnostic(transparent))]
+    UnmatchedBrace(#[from] UnmatchedBraceError),
+
+    #[error(transparent)]
+    #[cfg_attr(feature = "std", diagnostic(transparent))]
+    UnclosedBrace(#[from] UnclosedBraceError),
+
+    #[error(transparent)]
+    #[cfg_attr(feature = "std", diagnostic(transparent))]
+    NestedTooDeep(#[from] BraceDepthError),
+
+    #[error(transparent)]
+    #[cfg_attr(feature = "std", diagnostic(transparent))]
+    UnexpectedToken(#[from] UnexpectedTokenError),
+
+    #[error(transparent)]
+    #[cfg_attr(feature = "std", diagnostic(transparent))]
+    IdentTooLong(#[from] IdentLengthError),
+
+    #[error(transparent)]
+    #[cfg_attr(feature = "std", diagnostic(transparent))]
+    MismatchedDelimiter(#[from] DelimiterMismatchError),
+
+    #[error(transparent)]
+    #[cfg_attr(feature = "std", diagnostic(transparent))]
+    UnmatchedDelimiter(#[from] UnmatchedDelimiterError),
+
+    #[error(transparent)]
+    #[cfg_attr(feature = "std", diagnostic(transparent))]
+    UnclosedDelimiter(#[from] UnclosedDelimiterError),
+
+    #[error(transparent)]
+    #[cfg_attr(feature = "std", diagnostic(transparent))]
+    TooManyTokens(#[from] TokenLimitError),
+
+    #[error(transparent)]
+    #[cfg_attr(feature = "std", diagnostic(transparent))]
+    InputTooLarge(#[from] InputSizeError),
+}
+
+impl LexError {
+    /// The part of the source the error points at.
+    pub fn span(&self) -> Span {
+        let err_span = match self {
+            LexError::UnexpectedChar(e) => e.err_span,
+            LexError::UnexpectedSequence(e) => e.err_span,
+            LexError::UnterminatedString(e) => e.err_span,
+            LexError::InvalidChar(e) => e.err_span,
+            LexError::InvalidEscape(e) => e.err_span,
+            LexError::MissingDigits(e) => e.err_span,
+            LexError::MisplacedDigitSeparator(e) => e.err_span,
+            LexError::UnterminatedBlockComment(e) => e.err_span,
+            LexError::InvalidUtf8(e) => e.err_span,
+            LexError::UnmatchedBrace(e) => e.err_span,
+            LexError::UnclosedBrace(e) => e.err_span,
+            LexError::NestedTooDeep(e) => e.err_span,
+            LexError::UnexpectedToken(e) => e.err_span,
+            LexError::IdentTooLong(e) => e.err_span,
+            LexError::MismatchedDelimiter(e) => e.err_span,
+            LexError::UnmatchedDelimiter(e) => e.err_span,
+            LexError::UnclosedDelimiter(e) => e.err_span,
+            LexError::TooManyTokens(e) => e.err_span,
+            LexError::InputTooLarge(e) => e.err_span,
+        };
+        Span {
+            start: err_span.offset(),
+            len: err_span.len(),
+        }
+    }
+}
+
+/// An error of a [`crate::BufLexer`], which can also fail to read its input.
+#[cfg(feature = "std")]
 #[derive(Diagnostic, Debug, Error)]
+pub enum BufLexError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Lex(#[from] LexError),
+}
+
+#[derive(Debug, Error)]
+#[cfg_attr(feature = "std", derive(Diagnostic))]
 #[error("Unexpected EOF")]
 pub struct Eof;
 
-#[derive(Diagnostic, Debug, Error)]
-#[error("Unexpected token '{token}'")]
+#[derive(Debug, Error)]
+#[cfg_attr(feature = "std", derive(Diagnostic))]
+#[error("Unexpected token '{}'", Printable(*token))]
 pub struct SingleTokenError {
-    #[source_code]
-    pub(crate) src: String,
+    #[cfg_attr(feature = "std", source_code)]
+    pub(crate) src: ErrorSource,
 
     pub token: char,
 
-    #[label = "this input character"]
+    #[cfg_attr(feature = "std", label = "this input character")]
     pub(crate) err_span: SourceSpan,
 }
 
+// shows control chars as escapes like `\u{0}`, since they are invisible or
+// garble the terminal when printed as they are
+struct Printable(char);
+
+impl fmt::Display for Printable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_control() {
+            write!(f, "{}", self.0.escape_unicode())
+        } else {
+            write!(f, "{}", self.0)
+        }
+    }
+}
+
 impl SingleTokenError {
+    /// The 1-based line of the error within the lexed input, with lines
+    /// counted like in a [`crate::LineIndex`].
     pub fn line(&self) -> usize {
-        let until_unrecongized = &self.src[..=self.err_span.offset()];
-        until_unrecongized.lines().count()
+        line_of(&self.src.lines, self.err_span.offset() - self.src.base) + self.src.first_line - 1
+    }
+
+    /// The whole source line the error is on, without its line break.
+    pub fn line_text(&self) -> &str {
+        let line = self.line() - self.src.first_line + 1;
+        let start = self.src.lines[line - 1];
+        // the next line starts right after the `\n` of this one
+        let end = self
+            .src
+            .lines
+            .get(line)
+            .map_or(self.src.len(), |next| next - 1);
+        let line = &self.src[start..end];
+        line.strip_suffix('\r').unwrap_or(line)
     }
 }
+
+#[derive(Debug, Error)]
+#[cfg_attr(feature = "std", derive(Diagnostic))]
+#[error("Unexpected sequence '{sequence}'")]
+pub struct UnexpectedSequenceError {
+    #[cfg_attr(feature = "std", source_code)]
+    pub(crate) src: ErrorSource,
+
+    pub sequence: String,
+
+    #[cfg_attr(feature = "std", label = "these input characters")]
+    pub(crate) err_span: SourceSpan,
+}
+
+#[derive(Debug, Error)]
+#[cfg_attr(feature = "std", derive(Diagnostic))]
+#[error("Unterminated string")]
+pub struct StringTerminationError {
+    #[cfg_attr(feature = "std", source_code)]
+    pub(crate) src: ErrorSource,
+
+    #[cfg_attr(feature = "std", label = "this string literal")]
+    pub(crate) err_span: SourceSpan,
+}
+
+#[derive(Debug, Error)]
+#[cfg_attr(feature = "std", derive(Diagnostic))]
+#[error("Character literals must hold exactly one character")]
+pub struct CharLiteralError {
+    #[cfg_attr(feature = "std", source_code)]
+    pub(crate) src: ErrorSource,
+
+    #[cfg_attr(feature = "std", label = "this character literal")]
+    pub(crate) err_span: SourceSpan,
+}
+
+#[derive(Debug, Error)]
+#[cfg_attr(feature = "std", derive(Diagnostic))]
+#[error("Invalid escape sequence")]
+pub struct InvalidEscapeError {
+    #[cfg_attr(feature = "std", source_code)]
+    pub(crate) src: ErrorSource,
+
+    #[cfg_attr(feature = "std", label = "this escape sequence")]
+    pub(crate) err_span: SourceSpan,
+}
+
+#[derive(Debug, Error)]
+#[cfg_attr(feature = "std", derive(Diagnostic))]
+#[error("Missing digits after the integer prefix")]
+pub struct MissingDigitsError {
+    #[cfg_attr(feature = "std", source_code)]
+    pub(crate) src: ErrorSource,
+
+    #[cfg_attr(feature = "std", label = "this prefix")]
+    pub(crate) err_span: SourceSpan,
+}
+
+#[derive(Debug, Error)]
+#[cfg_attr(feature = "std", derive(Diagnostic))]
+#[error("Digit separators must be placed between two digits")]
+pub struct DigitSeparatorError {
+    #[cfg_attr(feature = "std", source_code)]
+    pub(crate) src: ErrorSource,
+
+    #[cfg_attr(feature = "std", label = "this separator")]
+    pub(crate) err_span: SourceSpan,
+}
+
+#[derive(Debug, Error)]
+#[cfg_attr(feature = "std", derive(Diagnostic))]
+#[error("Unterminated block comment")]
+pub struct BlockCommentTerminationError {
+    #[cfg_attr(feature = "std", source_code)]
+    pub(crate) src: ErrorSource,
+
+    #[cfg_attr(feature = "std", label = "this block comment")]
+    pub(crate) err_span: SourceSpan,
+}
+
+#[derive(Debug, Error)]
+#[cfg_attr(feature = "std", derive(Diagnostic))]
+#[error("Unknown identifier '{ident}'")]
+#[cfg_attr(
+    feature = "std",
+    diagnostic(severity(Warning), help("did you mean `{keyword}`?"))
+)]
+pub struct KeywordSuggestion {
+    #[cfg_attr(feature = "std", source_code)]
+    pub(crate) src: ErrorSource,
+
+    pub ident: String,
+
+    pub keyword: &'static str,
+
+    #[cfg_attr(feature = "std", label = "this identifier is close to a keyword")]
+    pub(crate) err_span: SourceSpan,
+}
+
+/// An identifier over the limit of [`crate::LexerBuilder::max_ident_len`].
+#[derive(Debug, Error)]
+#[cfg_attr(feature = "std", derive(Diagnostic))]
+#[error("Identifiers must not be longer than {max_len} chars")]
+pub struct IdentLengthError {
+    #[cfg_attr(feature = "std", source_code)]
+    pub(crate) src: ErrorSource,
+
+    pub max_len: usize,
+
+    #[cfg_attr(feature = "std", label = "this identifier")]
+    pub(crate) err_span: SourceSpan,
+}
+
+/// One token more than [`crate::LexerBuilder::max_tokens`] allows.
+#[derive(Debug, Error)]
+#[cfg_attr(feature = "std", derive(Diagnostic))]
+#[error("The input has more than {max_tokens} tokens")]
+pub struct TokenLimitError {
+    #[cfg_attr(feature = "std", source_code)]
+    pub(crate) src: ErrorSource,
+
+    pub max_tokens: usize,
+
+    #[cfg_attr(feature = "std", label = "this token is over the limit")]
+    pub(crate) err_span: SourceSpan,
+}
+
+/// An input longer than [`crate::LexerBuilder::max_bytes`] allows.
+#[derive(Debug, Error)]
+#[cfg_attr(feature = "std", derive(Diagnostic))]
+#[error("The input is longer than {max_bytes} bytes")]
+pub struct InputSizeError {
+    #[cfg_attr(feature = "std", source_code)]
+    pub(crate) src: ErrorSource,
+
+    pub max_bytes: usize,
+
+    #[cfg_attr(feature = "std", label = "this is over the limit")]
+    pub(crate) err_span: SourceSpan,
+}
+
+/// The source is not valid UTF-8; it is shown with the invalid bytes replaced
+/// by `�`.
+#[derive(Debug, Error)]
+#[cfg_attr(feature = "std", derive(Diagnostic))]
+#[error("Invalid UTF-8 at byte {offset}")]
+pub struct Utf8Error {
+    #[cfg_attr(feature = "std", source_code)]
+    pub(crate) src: ErrorSource,
+
+    /// The offset of the first invalid byte in the input.
+    pub offset: usize,
+
+    #[cfg_attr(feature = "std", label = "this byte sequence")]
+    pub(crate) err_span: SourceSpan,
+}
+
+#[derive(Debug, Error)]
+#[cfg_attr(feature = "std", derive(Diagnostic))]
+#[error("Unmatched closing brace")]
+pub struct UnmatchedBraceError {
+    #[cfg_attr(feature = "std", source_code)]
+    pub(crate) src: ErrorSource,
+
+    #[cfg_attr(feature = "std", label = "this brace closes nothing")]
+    pub(crate) err_span: SourceSpan,
+}
+
+#[derive(Debug, Error)]
+#[cfg_attr(feature = "std", derive(Diagnostic))]
+#[error("Unclosed brace")]
+pub struct UnclosedBraceError {
+    #[cfg_attr(feature = "std", source_code)]
+    pub(crate) src: ErrorSource,
+
+    #[cfg_attr(feature = "std", label = "this brace is never closed")]
+    pub(crate) err_span: SourceSpan,
+}
+
+/// A delimiter closed by another kind of delimiter, like `{` by `)`. The span
+/// of the error is the one of the closer.
+#[derive(Debug, Error)]
+#[cfg_attr(feature = "std", derive(Diagnostic))]
+#[error("Mismatched closing delimiter {found}, expected {expected}")]
+pub struct DelimiterMismatchError {
+    #[cfg_attr(feature = "std", source_code)]
+    pub(crate) src: ErrorSource,
+
+    pub expected: TokenKind,
+
+    pub found: TokenKind,
+
+    #[cfg_attr(feature = "std", label = "this delimiter is opened here")]
+    pub(crate) opener_span: SourceSpan,
+
+    #[cfg_attr(feature = "std", label = "and closed here")]
+    pub(crate) err_span: SourceSpan,
+}
+
+/// A `}`, `)` or `]` that [`crate::check_delimiters`] finds nothing open for.
+#[derive(Debug, Error)]
+#[cfg_attr(feature = "std", derive(Diagnostic))]
+#[error("Unmatched closing delimiter `{delimiter}`")]
+pub struct UnmatchedDelimiterError {
+    #[cfg_attr(feature = "std", source_code)]
+    pub(crate) src: ErrorSource,
+
+    pub delimiter: TokenKind,
+
+    #[cfg_attr(feature = "std", label = "this delimiter closes nothing")]
+    pub(crate) err_span: SourceSpan,
+}
+
+/// A `{`, `(` or `[` that [`crate::check_delimiters`] finds still open at the
+/// end of the tokens.
+#[derive(Debug, Error)]
+#[cfg_attr(feature = "std", derive(Diagnostic))]
+#[error("Unclosed delimiter `{delimiter}`")]
+pub struct UnclosedDelimiterError {
+    #[cfg_attr(feature = "std", source_code)]
+    pub(crate) src: ErrorSource,
+
+    pub delimiter: TokenKind,
+
+    #[cfg_attr(feature = "std", label = "this delimiter is never closed")]
+    pub(crate) err_span: SourceSpan,
+}
+
+#[derive(Debug, Error)]
+#[cfg_attr(feature = "std", derive(Diagnostic))]
+#[error("Braces are nested deeper than {max_depth} levels")]
+pub struct BraceDepthError {
+    #[cfg_attr(feature = "std", source_code)]
+    pub(crate) src: ErrorSource,
+
+    pub max_depth: usize,
+
+    #[cfg_attr(feature = "std", label = "this brace is one level too deep")]
+    pub(crate) err_span: SourceSpan,
+}
+
+/// A token of another kind than a parser expected, see
+/// [`crate::PeekableLexer::expect`].
+#[derive(Debug, Error)]
+#[cfg_attr(feature = "std", derive(Diagnostic))]
+#[error("Expected {expected}, found {found}")]
+pub struct UnexpectedTokenError {
+    #[cfg_attr(feature = "std", source_code)]
+    pub(crate) src: ErrorSource,
+
+    pub expected: TokenKind,
+
+    pub found: TokenKind,
+
+    #[cfg_attr(feature = "std", label = "this token")]
+    pub(crate) err_span: SourceSpan,
+}
@@ -0,0 +1,88 @@
+use alloc::{collections::BTreeMap, string::String, vec::Vec};
+
+/// The id of a string in an [`Interner`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Symbol(u32);
+
+/// A symbol table that hands out the same [`Symbol`] for equal strings, so
+/// repeated identifiers are stored once.
+///
+/// Symbols are only meaningful to the interner that made them.
+#[derive(Debug, Clone, Default)]
+pub struct Interner {
+    symbols: BTreeMap<String, Symbol>,
+    // the string of each symbol, indexed by its id
+    strings: Vec<String>,
+}
+
+impl Interner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn intern(&mut self, text: &str) -> Symbol {
+        if let Some(&symbol) = self.symbols.get(text) {
+            return symbol;
+        }
+        let id = u32::try_from(self.strings.len()).expect("more than u32::MAX symbols");
+        let symbol = Symbol(id);
+        self.strings.push(text.into());
+        self.symbols.insert(text.into(), symbol);
+        symbol
+    }
+
+    /// The string `symbol` was interned from.
+    ///
+    /// # Panics
+    ///
+    /// If `symbol` was made by another interner with more symbols.
+    pub fn resolve(&self, symbol: Symbol) -> &str {
+        &self.strings[symbol.0 as usize]
+    }
+
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Lexer, TokenKind};
+
+    use super::Interner;
+
+    #[test]
+    fn it_shares_symbols_between_equal_identifiers() {
+        let mut interner = Interner::new();
+        let symbols = Lexer::new("foo foo bar")
+            .interned(&mut interner)
+            .map(|token| token.unwrap().1)
+            .collect::<Vec<_>>();
+        let [Some(foo), Some(foo_again), Some(bar), None] = symbols[..] else {
+            panic!("should have interned three identifiers");
+        };
+        assert_eq!(foo, foo_again);
+        assert_ne!(foo, bar);
+        assert_eq!("foo", interner.resolve(foo));
+        assert_eq!("bar", interner.resolve(bar));
+        assert_eq!(2, interner.len());
+    }
+
+    #[test]
+    fn it_interns_keywords_but_not_other_tokens() {
+        let mut interner = Interner::new();
+        let tokens = Lexer::new("task task { \"task\" }")
+            .interned(&mut interner)
+            .map(|token| token.unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(TokenKind::Task, tokens[0].0.kind);
+        assert!(tokens[0].1.is_some());
+        assert_eq!(tokens[0].1, tokens[1].1);
+        assert!(tokens[2..].iter().all(|(_, symbol)| symbol.is_none()));
+        assert_eq!(1, interner.len());
+    }
+}
@@ -0,0 +1,91 @@
+use alloc::string::String;
+use core::fmt::Write;
+
+use crate::{render, LineIndex, Span};
+
+/// Lexes all of `input` into a JSON array for tooling outside of Rust.
+///
+/// Each token is an object of its `kind`, by its [`crate::TokenKind::name`],
+/// its byte `offset` and `len`, its `text` and its 1-based `line` and
+/// `column`. Lexing goes on past errors, which are objects of the `"error"`
+/// kind with a `message` in place of the `text`.
+pub fn tokens_to_json(input: &str) -> String {
+    let lines = LineIndex::new(input);
+
+    let mut json = String::from("[");
+    for (i, (span, kind, text)) in render::dump(input).iter().enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+        let Span { start, len } = *span;
+        let (line, column) = lines.line_col(start);
+        let text_key = if *kind == "error" { "message" } else { "text" };
+        write!(
+            json,
+            r#"{{"kind":"{kind}","offset":{start},"len":{len},"{text_key}":"#
+        )
+        .expect("writing to a String cannot fail");
+        push_json_string(&mut json, text);
+        write!(json, r#","line":{line},"column":{column}}}"#)
+            .expect("writing to a String cannot fail");
+    }
+    json.push(']');
+    json
+}
+
+fn push_json_string(json: &mut String, s: &str) {
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c.is_control() => {
+                // chars outside the BMP are never control chars, so one
+                // `\u` escape is enough
+                write!(json, "\\u{:04x}", c as u32).expect("writing to a String cannot fail");
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
+
+#[cfg(test)]
+mod test {
+    use super::tokens_to_json;
+
+    #[test]
+    fn it_dumps_tokens_as_json() {
+        assert_eq!(
+            concat!(
+                r#"[{"kind":"task","offset":0,"len":4,"text":"task","line":1,"column":1},"#,
+                r#"{"kind":"ident","offset":5,"len":3,"text":"foo","line":1,"column":6},"#,
+                r#"{"kind":"left_brace","offset":9,"len":1,"text":"{","line":1,"column":10},"#,
+                r#"{"kind":"right_brace","offset":10,"len":1,"text":"}","line":1,"column":11},"#,
+                r#"{"kind":"eof","offset":11,"len":0,"text":"","line":1,"column":12}]"#,
+            ),
+            tokens_to_json("task foo {}")
+        );
+    }
+
+    #[test]
+    fn it_dumps_errors_with_their_span() {
+        assert_eq!(
+            concat!(
+                r#"[{"kind":"ident","offset":0,"len":3,"text":"foo","line":1,"column":1},"#,
+                r#"{"kind":"error","offset":4,"len":1,"message":"Unexpected token '?'","line":2,"column":1},"#,
+                r#"{"kind":"eof","offset":5,"len":0,"text":"","line":2,"column":2}]"#,
+            ),
+            tokens_to_json("foo\n?")
+        );
+    }
+
+    #[test]
+    fn it_escapes_strings() {
+        let json = tokens_to_json("\"a\\\"\u{1}\"");
+        assert!(json.contains(r#""text":"\"a\\\"\u0001\"""#), "{json}");
+    }
+}
@@ -1,71 +1,1398 @@
-use error::SingleTokenError;
-use miette::{Error, SourceSpan};
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
 
-mod error;
+extern crate alloc;
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+use alloc::{
+    borrow::Cow,
+    boxed::Box,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use core::{cell::Cell, fmt, iter::Peekable, ops::Range};
+
+use error::{
+    BlockCommentTerminationError, CharLiteralError, DigitSeparatorError, ErrorSource,
+    IdentLengthError, InputSizeError, InvalidEscapeError, LexError, MissingDigitsError,
+    SingleTokenError, StringTerminationError, TokenLimitError, UnexpectedSequenceError, Utf8Error,
+};
+use span::SourceSpan;
+use unicode_ident::{is_xid_continue, is_xid_start};
+
+mod braces;
+#[cfg(feature = "std")]
+mod buffered;
+mod builder;
+pub mod error;
+mod interner;
+mod json;
+mod line_index;
+mod peek;
+mod render;
+mod span;
+mod stream;
+mod suggest;
+mod token_vec;
+
+pub use braces::{check_brace_depth, check_braces, check_delimiters};
+#[cfg(feature = "std")]
+pub use buffered::BufLexer;
+pub use builder::LexerBuilder;
+pub use interner::{Interner, Symbol};
+pub use json::tokens_to_json;
+pub use line_index::LineIndex;
+pub use peek::PeekableLexer;
+pub use render::{debug_table, render_tokens};
+pub use span::Span;
+pub use stream::TokenStream;
+pub use suggest::suggest_keywords;
+pub use token_vec::TokenVec;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum TokenKind {
     Task,
+    If,
+    Else,
+    For,
+    In,
+    Return,
+    True,
+    False,
     Ident,
+    /// Identifiers joined by dots like `plugin.subcommand`, only lexed as one
+    /// token with [`LexerBuilder::dotted_paths`].
+    Path,
+    String,
+    Char,
+    Integer,
+    Float,
+    /// An integer with a unit of time like `30s`, only lexed with
+    /// [`LexerBuilder::unit_suffixes`]. The units are `ms`, `s`, `m`, `h`
+    /// and `d`.
+    Duration,
+    /// An integer with a unit of size like `10MB`, only lexed with
+    /// [`LexerBuilder::unit_suffixes`]. The units are `B`, `KB`, `MB`, `GB`
+    /// and `TB`, and `KiB`, `MiB`, `GiB` and `TiB`.
+    ByteSize,
     LeftBrace,
     RightBrace,
+    LeftParen,
+    RightParen,
+    LeftBracket,
+    RightBracket,
+    Colon,
+    Comma,
+    Semicolon,
+    Dot,
+    Equals,
+    /// `-`, also the sign of a negative number: `-5` lexes as `Minus` followed
+    /// by `Integer` and the parser folds the two together.
+    Minus,
+    Plus,
+    Star,
+    /// `/`, unless it opens a comment.
+    Slash,
+    Percent,
+    Bang,
+    EqualEqual,
+    NotEqual,
+    Greater,
+    GreaterEqual,
+    /// `<`, unless it opens a heredoc.
+    Less,
+    LessEqual,
+    AmpAmp,
+    PipePipe,
+    /// `->`, as in `task a -> b {}` where `a` depends on `b`.
+    Arrow,
+    /// `--`, which separates the arguments passed on as they are, like in
+    /// `task build -- arg`.
+    ///
+    /// It is lexed before `->`, so `-->` is a `DashDash` followed by a
+    /// `Greater`, never a `Minus` followed by an `Arrow`.
+    DashDash,
+    /// `@`, which starts an attribute like `@cached` or `@retry(3)`.
+    At,
+    /// A variable reference like `$HOME`; its origin includes the `$`.
+    Variable,
+    /// A line break, only lexed with [`LexerBuilder::significant_newlines`].
+    ///
+    /// A run of whitespace with several line breaks in it, as between two
+    /// lines with blank lines in between, is one `Newline` from its first
+    /// line break through its last one.
+    Newline,
+    /// A run of whitespace, only lexed with [`LexerBuilder::trivia`].
+    ///
+    /// Whitespace is every char of the Unicode `White_Space` property, so a
+    /// no-break space (U+00A0) is whitespace too. Invisible chars without it,
+    /// like the zero-width space (U+200B), are unexpected tokens.
+    Whitespace,
+    Comment,
+    /// A char, or a run of punctuation, that begins no token, only lexed with
+    /// [`LexerBuilder::unknown_tokens`] in place of an error.
+    Unknown,
+    Eof,
+}
+
+/// The keywords a [`Lexer`] recognizes unless a [`LexerBuilder`] says otherwise.
+pub const DEFAULT_KEYWORDS: &[(&str, TokenKind)] = &[
+    ("task", TokenKind::Task),
+    ("if", TokenKind::If),
+    ("else", TokenKind::Else),
+    ("for", TokenKind::For),
+    ("in", TokenKind::In),
+    ("return", TokenKind::Return),
+    ("true", TokenKind::True),
+    ("false", TokenKind::False),
+];
+
+impl TokenKind {
+    pub fn is_keyword(&self) -> bool {
+        matches!(
+            self,
+            TokenKind::Task
+                | TokenKind::If
+                | TokenKind::Else
+                | TokenKind::For
+                | TokenKind::In
+                | TokenKind::Return
+                | TokenKind::True
+                | TokenKind::False
+        )
+    }
+
+    /// Whether the token carries a value; `true` and `false` are both keywords
+    /// and literals.
+    pub fn is_literal(&self) -> bool {
+        matches!(
+            self,
+            TokenKind::String
+                | TokenKind::Char
+                | TokenKind::Integer
+                | TokenKind::Float
+                | TokenKind::Duration
+                | TokenKind::ByteSize
+                | TokenKind::True
+                | TokenKind::False
+        )
+    }
+
+    /// Whether the token carries no meaning for a parser.
+    pub fn is_trivia(&self) -> bool {
+        matches!(self, TokenKind::Whitespace | TokenKind::Comment)
+    }
+
+    /// A name for the kind in snake case, like `left_brace`, for machines to
+    /// read where [`fmt::Display`] is meant for people.
+    ///
+    /// Names never change once a kind is added.
+    pub fn name(&self) -> &'static str {
+        match self {
+            TokenKind::Task => "task",
+            TokenKind::If => "if",
+            TokenKind::Else => "else",
+            TokenKind::For => "for",
+            TokenKind::In => "in",
+            TokenKind::Return => "return",
+            TokenKind::True => "true",
+            TokenKind::False => "false",
+            TokenKind::Ident => "ident",
+            TokenKind::Path => "path",
+            TokenKind::String => "string",
+            TokenKind::Char => "char",
+            TokenKind::Integer => "integer",
+            TokenKind::Float => "float",
+            TokenKind::Duration => "duration",
+            TokenKind::ByteSize => "byte_size",
+            TokenKind::LeftBrace => "left_brace",
+            TokenKind::RightBrace => "right_brace",
+            TokenKind::LeftParen => "left_paren",
+            TokenKind::RightParen => "right_paren",
+            TokenKind::LeftBracket => "left_bracket",
+            TokenKind::RightBracket => "right_bracket",
+            TokenKind::Colon => "colon",
+            TokenKind::Comma => "comma",
+            TokenKind::Semicolon => "semicolon",
+            TokenKind::Dot => "dot",
+            TokenKind::Equals => "equals",
+            TokenKind::Minus => "minus",
+            TokenKind::Plus => "plus",
+            TokenKind::Star => "star",
+            TokenKind::Slash => "slash",
+            TokenKind::Percent => "percent",
+            TokenKind::Bang => "bang",
+            TokenKind::EqualEqual => "equal_equal",
+            TokenKind::NotEqual => "not_equal",
+            TokenKind::Greater => "greater",
+            TokenKind::GreaterEqual => "greater_equal",
+            TokenKind::Less => "less",
+            TokenKind::LessEqual => "less_equal",
+            TokenKind::AmpAmp => "amp_amp",
+            TokenKind::PipePipe => "pipe_pipe",
+            TokenKind::Arrow => "arrow",
+            TokenKind::DashDash => "dash_dash",
+            TokenKind::At => "at",
+            TokenKind::Variable => "variable",
+            TokenKind::Newline => "newline",
+            TokenKind::Whitespace => "whitespace",
+            TokenKind::Comment => "comment",
+            TokenKind::Unknown => "unknown",
+            TokenKind::Eof => "eof",
+        }
+    }
+}
+
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TokenKind::Task => "task",
+            TokenKind::If => "if",
+            TokenKind::Else => "else",
+            TokenKind::For => "for",
+            TokenKind::In => "in",
+            TokenKind::Return => "return",
+            TokenKind::True => "true",
+            TokenKind::False => "false",
+            TokenKind::Ident => "identifier",
+            TokenKind::Path => "path",
+            TokenKind::String => "string",
+            TokenKind::Char => "character",
+            TokenKind::Integer => "integer",
+            TokenKind::Float => "float",
+            TokenKind::Duration => "duration",
+            TokenKind::ByteSize => "byte size",
+            TokenKind::LeftBrace => "{",
+            TokenKind::RightBrace => "}",
+            TokenKind::LeftParen => "(",
+            TokenKind::RightParen => ")",
+            TokenKind::LeftBracket => "[",
+            TokenKind::RightBracket => "]",
+            TokenKind::Colon => ":",
+            TokenKind::Comma => ",",
+            TokenKind::Semicolon => ";",
+            TokenKind::Dot => ".",
+            TokenKind::Equals => "=",
+            TokenKind::Minus => "-",
+            TokenKind::Plus => "+",
+            TokenKind::Star => "*",
+            TokenKind::Slash => "/",
+            TokenKind::Percent => "%",
+            TokenKind::Bang => "!",
+            TokenKind::EqualEqual => "==",
+            TokenKind::NotEqual => "!=",
+            TokenKind::Greater => ">",
+            TokenKind::GreaterEqual => ">=",
+            TokenKind::Less => "<",
+            TokenKind::LessEqual => "<=",
+            TokenKind::AmpAmp => "&&",
+            TokenKind::PipePipe => "||",
+            TokenKind::Arrow => "->",
+            TokenKind::DashDash => "--",
+            TokenKind::At => "@",
+            TokenKind::Variable => "variable",
+            TokenKind::Newline => "newline",
+            TokenKind::Whitespace => "whitespace",
+            TokenKind::Comment => "comment",
+            TokenKind::Unknown => "unknown token",
+            TokenKind::Eof => "end of input",
+        })
+    }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Token<'de> {
     pub origin: &'de str,
     pub offset: usize,
     pub kind: TokenKind,
+    pub(crate) whole: &'de str,
+    // the 1-based line of `offset` within `whole`
+    pub(crate) line: usize,
+    // the columns up to the next tab stop a tab counts as, see `column`
+    pub(crate) tab_width: usize,
+    // made by `merge_adjacent`, so `origin` need not look like `kind`
+    pub(crate) merged: bool,
+}
+
+/// Tokens are ordered by their position in the source, not by their kind.
+///
+/// Only tokens of different sources can share an offset; their text, and only
+/// then their kind, tab width and whether they were merged, breaks the tie
+/// so the order agrees with `==`.
+impl Ord for Token<'_> {
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        (
+            self.offset,
+            self.origin,
+            self.whole,
+            self.kind as u8,
+            self.tab_width,
+            self.merged,
+        )
+            .cmp(&(
+                other.offset,
+                other.origin,
+                other.whole,
+                other.kind as u8,
+                other.tab_width,
+                other.merged,
+            ))
+    }
+}
+
+impl PartialOrd for Token<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for Token<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            TokenKind::Ident
+            | TokenKind::Path
+            | TokenKind::String
+            | TokenKind::Char
+            | TokenKind::Integer
+            | TokenKind::Float
+            | TokenKind::Duration
+            | TokenKind::ByteSize
+            | TokenKind::Variable
+            | TokenKind::Comment
+            | TokenKind::Unknown => {
+                write!(f, "{} `{}`", self.kind, self.origin)
+            }
+            TokenKind::Newline | TokenKind::Whitespace | TokenKind::Eof => {
+                write!(f, "{}", self.kind)
+            }
+            // the kind already tells the text of keywords and punctuation
+            _ => write!(f, "`{}`", self.origin),
+        }
+    }
+}
+
+impl<'de> Token<'de> {
+    pub fn is_keyword(&self) -> bool {
+        self.kind.is_keyword()
+    }
+
+    /// The radix of an integer literal, given by its `0x`, `0o` or `0b` prefix.
+    pub fn radix(&self) -> Option<u32> {
+        if self.kind != TokenKind::Integer {
+            return None;
+        }
+        Some(match self.origin.get(..2) {
+            Some("0x") => 16,
+            Some("0o") => 8,
+            Some("0b") => 2,
+            _ => 10,
+        })
+    }
+
+    pub fn to_owned_token(&self) -> OwnedToken {
+        OwnedToken {
+            text: self.origin.to_string(),
+            offset: self.offset,
+            kind: self.kind,
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        Span {
+            start: self.offset,
+            len: self.origin.len(),
+        }
+    }
+
+    /// The start and end of the token in `src` as Language Server Protocol
+    /// positions, see [`Span::to_utf16`].
+    pub fn utf16_range(&self, src: &str) -> Range<(u32, u32)> {
+        let span = self.span();
+        span.to_utf16(src)..span::utf16_position(src, span.end())
+    }
+
+    /// The 1-based line the token starts on, within the lexed input, with
+    /// lines counted like in a [`LineIndex`].
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// The 1-based column the token starts at, counted in chars, where a tab
+    /// moves on to the next tab stop of the [`LexerBuilder::tab_width`] it was
+    /// lexed with.
+    pub fn column(&self) -> usize {
+        self.column_with_tab_width(self.tab_width)
+    }
+
+    /// Like [`Token::column`], with tab stops every `tab_width` columns to
+    /// match an editor, so with 4 the `b` of `a\tb` is at column 5.
+    ///
+    /// # Panics
+    ///
+    /// If `tab_width` is 0.
+    pub fn column_with_tab_width(&self, tab_width: usize) -> usize {
+        let offset = self.local_offset();
+        let line_start = self.whole[..offset]
+            .rfind('\n')
+            .map_or(0, |newline| newline + 1);
+        let columns = self.whole[line_start..offset].chars().fold(0, |column, c| {
+            if c == '\t' {
+                column + tab_width - column % tab_width
+            } else {
+                column + 1
+            }
+        });
+        columns + 1
+    }
+
+    // the offset of the token within `whole`, which differs from `offset` for
+    // a `Lexer::new_at`
+    fn local_offset(&self) -> usize {
+        let local = self.origin.as_ptr() as usize - self.whole.as_ptr() as usize;
+        debug_assert!(local + self.origin.len() <= self.whole.len());
+        local
+    }
+
+    // the source for an error about this token
+    pub(crate) fn error_source(&self) -> ErrorSource {
+        self.error_source_through(self)
+    }
+
+    // the source for an error about the tokens from this one through `last`:
+    // only the lines they are on and one line around them, like the context
+    // miette shows, so an error does not copy all of a long source
+    pub(crate) fn error_source_through(&self, last: &Token<'de>) -> ErrorSource {
+        debug_assert!(core::ptr::eq(self.whole, last.whole));
+        let line_start = |offset: usize| {
+            self.whole[..offset]
+                .rfind('\n')
+                .map_or(0, |newline| newline + 1)
+        };
+        // from the start of the line before the first token
+        let (start, first_line) = match line_start(self.local_offset()) {
+            0 => (0, self.line),
+            start => (line_start(start - 1), self.line - 1),
+        };
+        // through the end of the line after the last token
+        let end = last.local_offset() + last.origin.len();
+        let end = self.whole[end..]
+            .match_indices('\n')
+            .nth(1)
+            .map_or(self.whole.len(), |(newline, _)| end + newline + 1);
+        let text = &self.whole[start..end];
+        ErrorSource {
+            text: text.into(),
+            base: self.offset - self.local_offset() + start,
+            first_line,
+            lines: LineIndex::new(text).line_starts,
+        }
+    }
+
+    /// The text of the token without its delimiters: literals lose their
+    /// quotes, raw strings and heredocs their fences, and variables their `$`.
+    ///
+    /// Escape sequences are left as written, see [`Token::unescaped`]. Any
+    /// other token kind, and any token of [`merge_adjacent`], is returned as
+    /// its `origin`.
+    pub fn text(&self) -> &'de str {
+        if self.merged {
+            return self.origin;
+        }
+        match self.kind {
+            TokenKind::Variable => &self.origin[1..],
+            TokenKind::String if self.origin.starts_with("<<") => {
+                let body_at = self.origin.find('\n').expect("heredocs span several lines") + 1;
+                let body_end = self
+                    .origin
+                    .rfind('\n')
+                    .expect("heredocs span several lines")
+                    + 1;
+                &self.origin[body_at..body_end.max(body_at)]
+            }
+            TokenKind::String if self.origin.starts_with('r') => {
+                let raw = &self.origin[1..];
+                let hashes = raw_string_hashes(raw).expect("raw strings start with r#*\"");
+                &raw[hashes + 1..raw.len() - hashes - 1]
+            }
+            TokenKind::String | TokenKind::Char => &self.origin[1..self.origin.len() - 1],
+            _ => self.origin,
+        }
+    }
+
+    /// Decodes the escape sequences of a string or character literal, without
+    /// its quotes.
+    ///
+    /// Raw strings and heredocs are returned without their delimiters, any
+    /// other token kind, and any token of [`merge_adjacent`], is returned as
+    /// its `origin`.
+    pub fn unescaped(&self) -> Result<Cow<'de, str>, LexError> {
+        if self.merged || !matches!(self.kind, TokenKind::String | TokenKind::Char) {
+            return Ok(Cow::Borrowed(self.origin));
+        }
+
+        // neither raw strings nor heredocs have escapes
+        let inner = self.text();
+        if self.origin.starts_with(['<', 'r']) || !inner.contains('\\') {
+            return Ok(Cow::Borrowed(inner));
+        }
+
+        let mut unescaped = String::with_capacity(inner.len());
+        let mut chars = inner.char_indices();
+        while let Some((i, c)) = chars.next() {
+            if c != '\\' {
+                unescaped.push(c);
+                continue;
+            }
+
+            let (j, e) = chars.next().expect("literals never end in a backslash");
+            let Some(e) = unescape_char(e) else {
+                // +1 for the opening quote that is not part of `inner`
+                let inner_at = self.offset + 1;
+                return Err(InvalidEscapeError {
+                    src: self.error_source(),
+                    err_span: SourceSpan::from(inner_at + i..inner_at + j + e.len_utf8()),
+                }
+                .into());
+            };
+            unescaped.push(e);
+        }
+
+        Ok(Cow::Owned(unescaped))
+    }
+}
+
+/// A [`Token`] that owns its text instead of borrowing it from the source.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OwnedToken {
+    pub text: String,
+    pub offset: usize,
+    pub kind: TokenKind,
+}
+
+// the number of `#` of a raw string, if `after_r` continues one after its `r`
+fn raw_string_hashes(after_r: &str) -> Option<usize> {
+    let hashes = after_r.len() - after_r.trim_start_matches('#').len();
+    after_r[hashes..].starts_with('"').then_some(hashes)
+}
+
+/// The tag of a heredoc, if `after_lt` continues one after its first `<`.
+///
+/// A heredoc opens with `<<TAG` at the end of a line and its body spans the
+/// following lines up to a line holding just `TAG`. That closing line may be
+/// indented, but nothing else may follow the tag on it, so the tag appearing
+/// within a line of the body does not end the heredoc.
+fn heredoc_tag(after_lt: &str) -> Option<&str> {
+    let tag = after_lt.strip_prefix('<')?;
+    if !starts_ident(tag) {
+        return None;
+    }
+    let (tag, after) = tag.split_at(ident_end(tag));
+    (after.starts_with('\n') || after.starts_with("\r\n")).then_some(tag)
+}
+
+// which bytes continue an identifier, for the ASCII fast path of `ident_end`
+const ASCII_IDENT_CONTINUE: [bool; 256] = {
+    let mut table = [false; 256];
+    let mut b = 0;
+    while b < 128 {
+        table[b] = (b as u8).is_ascii_alphanumeric() || b == b'_' as usize;
+        b += 1;
+    }
+    table
+};
+
+// the length of the identifier chars `s` starts with; plain ASCII is scanned
+// byte by byte and only a non-ASCII byte falls back to checking chars
+fn ident_end(s: &str) -> usize {
+    let ascii_end = s
+        .bytes()
+        .position(|b| !ASCII_IDENT_CONTINUE[b as usize])
+        .unwrap_or(s.len());
+    match s.as_bytes().get(ascii_end) {
+        Some(b) if !b.is_ascii() => ascii_end + ident_end_by_chars(&s[ascii_end..]),
+        _ => ascii_end,
+    }
+}
+
+fn ident_end_by_chars(s: &str) -> usize {
+    s.find(|c| !is_xid_continue(c)).unwrap_or(s.len())
+}
+
+// the kind of an integer followed by `suffix` with `unit_suffixes`
+fn unit_suffix(suffix: &str) -> Option<TokenKind> {
+    match suffix {
+        "ms" | "s" | "m" | "h" | "d" => Some(TokenKind::Duration),
+        "B" | "KB" | "MB" | "GB" | "TB" | "KiB" | "MiB" | "GiB" | "TiB" => {
+            Some(TokenKind::ByteSize)
+        }
+        _ => None,
+    }
+}
+
+fn starts_ident(s: &str) -> bool {
+    s.starts_with(|c| c == '_' || is_xid_start(c))
+}
+
+// the Unicode `White_Space` chars, most sources only have ASCII ones
+fn is_whitespace(c: char) -> bool {
+    if c.is_ascii() {
+        c.is_ascii_whitespace() || c == '\x0b'
+    } else {
+        c.is_whitespace()
+    }
+}
+
+// whether `s` starts with ASCII punctuation that begins no token, keep in sync
+// with the punctuation handled by `Lexer::next`
+fn is_unexpected_punctuation(s: &str) -> bool {
+    let Some(c) = s.chars().next() else {
+        return false;
+    };
+    let after = &s[c.len_utf8()..];
+    match c {
+        '{' | '}' | '(' | ')' | '[' | ']' | ':' | ',' | ';' | '.' | '=' | '-' | '+' | '*' | '/'
+        | '%' | '@' | '"' | '\'' | '#' | '_' | '!' | '<' | '>' => false,
+        '&' | '|' => !after.starts_with(c),
+        '$' => !starts_ident(after),
+        c => c.is_ascii_punctuation(),
+    }
+}
+
+// whether all of `word` after its `0x`, `0o` or `0b` are digits of `radix`
+// and separators, with at least one digit
+fn is_prefixed_integer(word: &str, radix: u32) -> bool {
+    let digits = &word[2..];
+    digits.contains(|c: char| c.is_digit(radix))
+        && digits.chars().all(|c| c == '_' || c.is_digit(radix))
+}
+
+fn newlines(bytes: &[u8]) -> usize {
+    bytes.iter().filter(|&&b| b == b'\n').count()
+}
+
+// whether `s` has more than `max` chars, without counting them when it has no
+// more than `max` bytes
+fn is_longer_than(s: &str, max: usize) -> bool {
+    s.len() > max && s.chars().count() > max
+}
+
+fn unescape_char(c: char) -> Option<char> {
+    match c {
+        'n' => Some('\n'),
+        't' => Some('\t'),
+        'r' => Some('\r'),
+        '0' => Some('\0'),
+        '"' => Some('"'),
+        '\'' => Some('\''),
+        '\\' => Some('\\'),
+        _ => None,
+    }
+}
+
+fn digits_end(s: &str) -> usize {
+    s.find(|c| !matches!(c, '0'..='9' | '_')).unwrap_or(s.len())
+}
+
+// separators are only allowed between two digits
+fn misplaced_separator(number: &str, digits: Range<usize>) -> Option<Range<usize>> {
+    let part = &number[digits.clone()];
+    if let Some(at) = part.find("__") {
+        Some(digits.start + at..digits.start + at + 2)
+    } else if part.ends_with('_') {
+        Some(digits.end - 1..digits.end)
+    } else {
+        None
+    }
 }
 
 pub struct Lexer<'de> {
     whole: &'de str,
     rest: &'de str,
+    // the offset of `whole` in the source that offsets are reported against
+    base: usize,
+    // the absolute offset of `rest`
     byte: usize,
+    reached_eof: bool,
+    shared_whole: Option<Arc<str>>,
+    // the line starts of `whole` for its errors, found on the first error
+    shared_lines: Option<Arc<[usize]>>,
+    // a byte of `whole` and its line, for the line of the next token to be
+    // counted from the last one
+    line_at: Cell<(usize, usize)>,
+    keywords: &'de [(&'de str, TokenKind)],
+    trivia: bool,
+    tab_width: usize,
+    dotted_paths: bool,
+    significant_newlines: bool,
+    max_ident_len: Option<usize>,
+    unit_suffixes: bool,
+    allow_leading_digit_idents: bool,
+    line_continuations: bool,
+    unknown_tokens: bool,
+    max_tokens: Option<usize>,
+    max_bytes: Option<usize>,
+    // the tokens lexed so far, for `max_tokens`
+    tokens_lexed: usize,
+    on_error: Option<Box<dyn FnMut(LexError) + Send + 'de>>,
 }
 
 impl<'de> Lexer<'de> {
     pub fn new(input: &'de str) -> Self {
-        Self {
-            whole: input,
-            rest: input,
-            byte: 0,
+        LexerBuilder::new().build(input)
+    }
+
+    /// Lexes `input` as the part of a larger source that starts at the byte
+    /// `offset`, to re-lex only a changed region of it.
+    ///
+    /// Token offsets and error spans are absolute, so `offset` is added to
+    /// them. Lines and columns still count from the start of `input`.
+    pub fn new_at(input: &'de str, offset: usize) -> Self {
+        let mut lexer = Self::new(input);
+        lexer.base = offset;
+        lexer.reset();
+        lexer
+    }
+
+    /// Lexes `input` if it is valid UTF-8, and points at its first invalid byte
+    /// otherwise.
+    pub fn from_bytes(input: &'de [u8]) -> Result<Self, LexError> {
+        match core::str::from_utf8(input) {
+            Ok(input) => Ok(Self::new(input)),
+            Err(e) => {
+                let offset = e.valid_up_to();
+                let text: Arc<str> = String::from_utf8_lossy(input).into();
+                Err(Utf8Error {
+                    src: ErrorSource {
+                        lines: LineIndex::new(&text).line_starts,
+                        text,
+                        base: 0,
+                        first_line: 1,
+                    },
+                    offset,
+                    // the invalid bytes are shown as a single `�`
+                    err_span: SourceSpan::from(offset..offset + '\u{fffd}'.len_utf8()),
+                }
+                .into())
+            }
+        }
+    }
+
+    pub fn source(&self) -> &'de str {
+        self.whole
+    }
+
+    /// The byte offset of the next char to be lexed, counting from the start
+    /// of the source for a [`Lexer::new_at`].
+    pub fn byte_offset(&self) -> usize {
+        self.byte
+    }
+
+    /// The part of the source that is not lexed yet, starting at
+    /// [`Lexer::byte_offset`].
+    pub fn rest(&self) -> &'de str {
+        self.rest
+    }
+
+    /// Whether all of the source is lexed, though the [`TokenKind::Eof`] may
+    /// still be to come.
+    pub fn is_at_end(&self) -> bool {
+        self.rest.is_empty()
+    }
+
+    /// The number of bytes that are not lexed yet, as in [`Lexer::rest`].
+    pub fn remaining_bytes(&self) -> usize {
+        self.rest.len()
+    }
+
+    /// Starts lexing the same source again from its beginning.
+    pub fn reset(&mut self) {
+        // some editors start files with a byte order mark, which is no token,
+        // but a region of a source never starts at it
+        self.rest = match self.whole.strip_prefix('\u{feff}') {
+            Some(rest) if self.base == 0 => rest,
+            _ => self.whole,
+        };
+        self.byte = self.base + self.whole.len() - self.rest.len();
+        self.reached_eof = false;
+        self.tokens_lexed = 0;
+    }
+
+    /// Starts lexing `input` from its beginning, keeping the configuration.
+    pub fn reset_with(&mut self, input: &'de str) {
+        self.whole = input;
+        self.line_at.set((0, 1));
+        self.shared_whole = None;
+        self.shared_lines = None;
+        self.reset();
+    }
+
+    /// Goes on lexing right after `token`, for a source that grew since
+    /// `token` was lexed from it and was swapped in with
+    /// [`Lexer::reset_with`].
+    ///
+    /// Everything up to the end of `token` must be unchanged. The last token
+    /// before the end of the old source may run on into the appended text,
+    /// like `foo` into `foobar`, so resume after the one before it.
+    pub fn resume_from(&mut self, token: &Token) {
+        let end = token.offset + token.origin.len();
+        debug_assert_eq!(
+            Some(token.origin),
+            self.whole.get(token.offset - self.base..end - self.base),
+            "the source changed at `token`"
+        );
+        self.rest = &self.whole[end - self.base..];
+        self.byte = end;
+        self.reached_eof = false;
+    }
+
+    /// The columns between tab stops for [`Token::column`], as set by
+    /// [`LexerBuilder::tab_width`].
+    pub fn tab_width(&self) -> usize {
+        self.tab_width
+    }
+
+    /// Emits whitespace and comments as [`TokenKind::Whitespace`] and
+    /// [`TokenKind::Comment`] tokens instead of skipping them.
+    pub fn with_trivia(mut self) -> Self {
+        self.trivia = true;
+        self
+    }
+
+    // moves past the next `n` bytes, which must end on a char boundary
+    fn advance(&mut self, n: usize) {
+        debug_assert!(
+            self.rest.is_char_boundary(n),
+            "advanced into the middle of a char at byte {}",
+            self.byte + n
+        );
+        self.rest = &self.rest[n..];
+        self.byte += n;
+        debug_assert_eq!(self.base + self.whole.len() - self.rest.len(), self.byte);
+    }
+
+    // the token from the byte `start` through the next `n` bytes
+    fn token_through(&mut self, start: usize, n: usize, kind: TokenKind) -> Token<'de> {
+        self.advance(n);
+        self.token_since(start, kind)
+    }
+
+    // the token of `origin`, which starts at the byte `offset`
+    fn token(&self, origin: &'de str, offset: usize, kind: TokenKind) -> Token<'de> {
+        Token {
+            origin,
+            offset,
+            kind,
+            whole: self.whole,
+            line: self.line_of(offset - self.base),
+            tab_width: self.tab_width,
+            merged: false,
+        }
+    }
+
+    // the 1-based line of the byte `local` of `whole`, tokens mostly come
+    // after the one before so only the line breaks between them are counted
+    fn line_of(&self, local: usize) -> usize {
+        let (at, line) = self.line_at.get();
+        let bytes = self.whole.as_bytes();
+        let line = if local >= at {
+            line + newlines(&bytes[at..local])
+        } else {
+            line - newlines(&bytes[local..at])
+        };
+        self.line_at.set((local, line));
+        line
+    }
+
+    // the token from the byte `start` up to everything lexed so far
+    fn token_since(&self, start: usize, kind: TokenKind) -> Token<'de> {
+        debug_assert!(self.whole.is_char_boundary(start - self.base));
+        self.token(
+            &self.whole[start - self.base..self.byte - self.base],
+            start,
+            kind,
+        )
+    }
+
+    // errors share a single copy of the source, made on the first error
+    fn shared_source(&mut self) -> ErrorSource {
+        ErrorSource {
+            text: Arc::clone(self.shared_whole.get_or_insert_with(|| self.whole.into())),
+            base: self.base,
+            first_line: 1,
+            lines: Arc::clone(
+                self.shared_lines
+                    .get_or_insert_with(|| LineIndex::new(self.whole).line_starts),
+            ),
+        }
+    }
+
+    /// Drops the [`TokenKind::is_trivia`] tokens, so a lexer
+    /// [`Lexer::with_trivia`] can feed a parser that has no use for them.
+    pub fn without_trivia(self) -> impl Iterator<Item = Result<Token<'de>, LexError>> {
+        self.filter(|token| !matches!(token, Ok(token) if token.kind.is_trivia()))
+    }
+
+    /// Pairs every token with its byte range, like `logos` and LALRPOP expect.
+    pub fn spanned(self) -> impl Iterator<Item = Result<(Token<'de>, Range<usize>), LexError>> {
+        self.map(|token| {
+            token.map(|token| {
+                let range = token.offset..token.offset + token.origin.len();
+                (token, range)
+            })
+        })
+    }
+
+    /// [`Lexer::spanned`] with a look at the next token ahead, for parsers that
+    /// need both.
+    pub fn peekable_spanned(
+        self,
+    ) -> Peekable<impl Iterator<Item = Result<(Token<'de>, Range<usize>), LexError>>> {
+        self.spanned().peekable()
+    }
+
+    /// Pairs every identifier and keyword with its [`Symbol`] in `interner`,
+    /// and every other token with `None`.
+    pub fn interned<'i>(
+        self,
+        interner: &'i mut Interner,
+    ) -> impl Iterator<Item = Result<(Token<'de>, Option<Symbol>), LexError>> + 'i
+    where
+        'de: 'i,
+    {
+        self.map(|token| {
+            token.map(|token| {
+                let symbol = (token.kind == TokenKind::Ident || token.is_keyword())
+                    .then(|| interner.intern(token.origin));
+                (token, symbol)
+            })
+        })
+    }
+
+    /// Lexes the rest of the input, collecting every error instead of
+    /// stopping at the first one.
+    ///
+    /// Each error consumes the offending input, so lexing always makes
+    /// progress. The errors are sorted by their offset, ties kept in the
+    /// order they were found.
+    pub fn lex_all(&mut self) -> (Vec<Token<'de>>, Vec<LexError>) {
+        let mut tokens = Vec::new();
+        let mut errors = Vec::new();
+        for token in self {
+            match token {
+                Ok(token) => tokens.push(token),
+                Err(e) => errors.push(e),
+            }
+        }
+        // errors come in source order already, but tools rely on the order
+        errors.sort_by_key(|e| e.span().start);
+        (tokens, errors)
+    }
+
+    /// Like [`tokenize`], but fills `buf` with the tokens of the rest of the
+    /// input instead of allocating a new `Vec`.
+    ///
+    /// `buf` is cleared first; on an error it holds the tokens before it.
+    pub fn tokenize_into(&mut self, buf: &mut Vec<Token<'de>>) -> Result<(), LexError> {
+        buf.clear();
+        for token in self {
+            buf.push(token?);
+        }
+        Ok(())
+    }
+}
+
+/// Lexes the whole input, stopping at the first error.
+///
+/// ```
+/// use barelang_lexer::{tokenize, TokenKind};
+///
+/// let tokens = tokenize("task foo {}").unwrap();
+/// assert_eq!("foo", tokens[1].origin);
+/// assert_eq!(TokenKind::Eof, tokens[4].kind);
+/// ```
+pub fn tokenize(input: &str) -> Result<Vec<Token<'_>>, LexError> {
+    Lexer::new(input).collect()
+}
+
+/// Like [`tokenize`], but only keeps the kind of each token.
+///
+/// ```
+/// use barelang_lexer::{tokenize_kinds, TokenKind};
+///
+/// assert_eq!(
+///     vec![
+///         TokenKind::Task,
+///         TokenKind::Ident,
+///         TokenKind::LeftBrace,
+///         TokenKind::RightBrace,
+///         TokenKind::Eof,
+///     ],
+///     tokenize_kinds("task foo {}").unwrap()
+/// );
+/// ```
+pub fn tokenize_kinds(input: &str) -> Result<Vec<TokenKind>, LexError> {
+    Lexer::new(input).map(|t| t.map(|t| t.kind)).collect()
+}
+
+/// The number of tokens in `input`, without collecting them and not counting
+/// the final [`TokenKind::Eof`].
+///
+/// ```
+/// assert_eq!(4, barelang_lexer::count_tokens("task foo {}").unwrap());
+/// ```
+pub fn count_tokens(input: &str) -> Result<usize, LexError> {
+    let mut count = 0;
+    for token in Lexer::new(input) {
+        if token?.kind != TokenKind::Eof {
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+/// The tokens of `kind` in `input`, like the names after `task` for
+/// [`TokenKind::Ident`].
+///
+/// All of `input` is lexed, so this fails on the first error even past the
+/// last token of `kind`.
+pub fn tokens_of_kind(input: &str, kind: TokenKind) -> Result<Vec<Token<'_>>, LexError> {
+    let mut tokens = Vec::new();
+    for token in Lexer::new(input) {
+        let token = token?;
+        if token.kind == kind {
+            tokens.push(token);
+        }
+    }
+    Ok(tokens)
+}
+
+/// The byte range of the first error in `input`, for tools that do without
+/// `miette` spans, or `None` if all of `input` lexes.
+///
+/// ```
+/// assert_eq!(Some(5..6), barelang_lexer::first_error_span("task ?"));
+/// assert_eq!(None, barelang_lexer::first_error_span("task foo {}"));
+/// ```
+pub fn first_error_span(input: &str) -> Option<Range<usize>> {
+    let span = Lexer::new(input).find_map(Result::err)?.span();
+    Some(span.start..span.end())
+}
+
+/// The text of `src` from the start of `start` through the end of `end`, which
+/// must not come before `start`.
+pub fn source_between<'de>(src: &'de str, start: &Token, end: &Token) -> &'de str {
+    debug_assert!(
+        start.offset <= end.offset,
+        "`end` at byte {} comes before `start` at byte {}",
+        end.offset,
+        start.offset
+    );
+    &src[start.offset..end.offset + end.origin.len()]
+}
+
+/// Coalesces neighboring `tokens` of `src` into one token of the kind `f`
+/// returns for them, to build multi-char tokens the lexer does not know.
+///
+/// The merged token spans from the start of the first token through the end
+/// of the second, with whatever is between them, so `f` decides whether a gap
+/// may be merged over. It is then handed to `f` again with the next token.
+///
+/// Whatever the kind, [`Token::text`] and [`Token::unescaped`] of a merged
+/// token are its whole `origin`, since it need not have the delimiters of a
+/// literal of that kind.
+pub fn merge_adjacent<'de>(
+    tokens: &[Token<'de>],
+    src: &'de str,
+    f: impl Fn(&Token<'de>, &Token<'de>) -> Option<TokenKind>,
+) -> Vec<Token<'de>> {
+    let mut merged: Vec<Token<'de>> = Vec::with_capacity(tokens.len());
+    for token in tokens {
+        if let Some(last) = merged.last_mut() {
+            if let Some(kind) = f(last, token) {
+                *last = Token {
+                    origin: source_between(src, last, token),
+                    kind,
+                    merged: true,
+                    ..*last
+                };
+                continue;
+            }
         }
+        merged.push(*token);
     }
+    merged
+}
+
+/// The token of `tokens`, which must be sorted by offset, that the byte
+/// `offset` is in, if it is not between two tokens.
+pub fn token_at<'t, 'de>(tokens: &'t [Token<'de>], offset: usize) -> Option<&'t Token<'de>> {
+    let after = tokens.partition_point(|token| token.offset <= offset);
+    let token = tokens[..after].last()?;
+    (offset < token.offset + token.origin.len()).then_some(token)
 }
 
 impl<'de> Iterator for Lexer<'de> {
-    type Item = Result<Token<'de>, Error>;
+    type Item = Result<Token<'de>, LexError>;
 
     fn next(&mut self) -> Option<Self::Item> {
         loop {
-            let mut chars = self.rest.chars();
-            let c = chars.next()?;
+            let token = self.checked_next()?;
+            match (token, self.on_error.as_mut()) {
+                (Err(e), Some(on_error)) => on_error(e),
+                (token, _) => return Some(token),
+            }
+        }
+    }
+}
+
+impl<'de> Lexer<'de> {
+    // the next token or error within the limits
+    fn checked_next(&mut self) -> Option<Result<Token<'de>, LexError>> {
+        if let Some(max_bytes) = self.max_bytes.filter(|&max| self.whole.len() > max) {
+            if self.reached_eof {
+                return None;
+            }
+            let mut limit = max_bytes;
+            while !self.whole.is_char_boundary(limit) {
+                limit -= 1;
+            }
+            let err = InputSizeError {
+                src: self.shared_source(),
+                max_bytes,
+                err_span: SourceSpan::from(self.base + limit..self.base + self.whole.len()),
+            };
+            return Some(Err(self.stop(err.into())));
+        }
+
+        let token = self.lex_next()?;
+        if let (Ok(token), Some(max_tokens)) = (&token, self.max_tokens) {
+            if token.kind != TokenKind::Eof {
+                if self.tokens_lexed == max_tokens {
+                    let err = TokenLimitError {
+                        src: self.shared_source(),
+                        max_tokens,
+                        err_span: token.span().into(),
+                    };
+                    return Some(Err(self.stop(err.into())));
+                }
+                self.tokens_lexed += 1;
+            }
+        }
+        Some(token)
+    }
+
+    // ends the lexing with `err`, without an Eof
+    fn stop(&mut self, err: LexError) -> LexError {
+        self.advance(self.rest.len());
+        self.reached_eof = true;
+        err
+    }
+
+    fn lex_next(&mut self) -> Option<Result<Token<'de>, LexError>> {
+        loop {
+            let Some(c) = self.rest.chars().next() else {
+                if self.reached_eof {
+                    return None;
+                }
+                self.reached_eof = true;
+                return Some(Ok(self.token(
+                    &self.whole[self.whole.len()..],
+                    self.base + self.whole.len(),
+                    TokenKind::Eof,
+                )));
+            };
             let c_at = self.byte;
             let c_str = &self.rest[..c.len_utf8()];
             let c_onwards = self.rest;
 
-            self.rest = chars.as_str();
-            self.byte += c.len_utf8();
+            self.advance(c.len_utf8());
 
-            let just = |kind: TokenKind| Token {
-                origin: c_str,
-                offset: c_at,
-                kind,
-            };
+            let just = |kind: TokenKind| self.token(c_str, c_at, kind);
 
-            enum Started {
+            enum Started<'de> {
                 Ident,
+                String,
+                Char,
+                RawString { hashes: usize },
+                Heredoc { tag: &'de str },
+                Number,
             }
 
             let started = match c {
                 '{' => return Some(Ok(just(TokenKind::LeftBrace))),
                 '}' => return Some(Ok(just(TokenKind::RightBrace))),
+                '(' => return Some(Ok(just(TokenKind::LeftParen))),
+                ')' => return Some(Ok(just(TokenKind::RightParen))),
+                '[' => return Some(Ok(just(TokenKind::LeftBracket))),
+                ']' => return Some(Ok(just(TokenKind::RightBracket))),
+                ':' => return Some(Ok(just(TokenKind::Colon))),
+                ',' => return Some(Ok(just(TokenKind::Comma))),
+                ';' => return Some(Ok(just(TokenKind::Semicolon))),
+                '.' => return Some(Ok(just(TokenKind::Dot))),
+                // two-char operators peek at `self.rest` before their one-char
+                // prefix gets a chance
+                '=' if self.rest.starts_with('=') => {
+                    return Some(Ok(self.token_through(c_at, 1, TokenKind::EqualEqual)))
+                }
+                '=' => return Some(Ok(just(TokenKind::Equals))),
+                '!' if self.rest.starts_with('=') => {
+                    return Some(Ok(self.token_through(c_at, 1, TokenKind::NotEqual)))
+                }
+                '!' => return Some(Ok(just(TokenKind::Bang))),
+                '>' if self.rest.starts_with('=') => {
+                    return Some(Ok(self.token_through(c_at, 1, TokenKind::GreaterEqual)))
+                }
+                '>' => return Some(Ok(just(TokenKind::Greater))),
+                '&' if self.rest.starts_with('&') => {
+                    return Some(Ok(self.token_through(c_at, 1, TokenKind::AmpAmp)))
+                }
+                '|' if self.rest.starts_with('|') => {
+                    return Some(Ok(self.token_through(c_at, 1, TokenKind::PipePipe)))
+                }
+                '@' => return Some(Ok(just(TokenKind::At))),
+                // a `$` without a name is left to the error arms below
+                '$' if starts_ident(self.rest) => {
+                    self.advance(ident_end(self.rest));
+                    return Some(Ok(self.token_since(c_at, TokenKind::Variable)));
+                }
+                '-' if self.rest.starts_with('-') => {
+                    return Some(Ok(self.token_through(c_at, 1, TokenKind::DashDash)))
+                }
+                '-' if self.rest.starts_with('>') => {
+                    return Some(Ok(self.token_through(c_at, 1, TokenKind::Arrow)))
+                }
+                // numbers are never negative, `-5` is a `Minus` followed by an
+                // `Integer` for the parser to combine
+                '-' => return Some(Ok(just(TokenKind::Minus))),
+                '+' => return Some(Ok(just(TokenKind::Plus))),
+                '*' => return Some(Ok(just(TokenKind::Star))),
+                '%' => return Some(Ok(just(TokenKind::Percent))),
+                '"' => Started::String,
+                '\'' => Started::Char,
+                'r' => match raw_string_hashes(self.rest) {
+                    Some(hashes) => Started::RawString { hashes },
+                    None => Started::Ident,
+                },
+                '<' if heredoc_tag(self.rest).is_some() => Started::Heredoc {
+                    tag: heredoc_tag(self.rest).expect("checked by the guard"),
+                },
+                '<' if self.rest.starts_with('=') => {
+                    return Some(Ok(self.token_through(c_at, 1, TokenKind::LessEqual)))
+                }
+                '<' => return Some(Ok(just(TokenKind::Less))),
+                '0'..='9' => Started::Number,
                 'a'..='z' | 'A'..='Z' | '_' => Started::Ident,
-                c if c.is_whitespace() => continue,
+                c if is_xid_start(c) => Started::Ident,
+                // `#` and `//` both comment out the rest of the line, whose
+                // newline is left for the whitespace handling
+                '#' | '/' if c == '#' || self.rest.starts_with('/') => {
+                    let end_of_line = self.rest.find('\n').unwrap_or(self.rest.len());
+                    self.advance(end_of_line);
+                    if self.trivia {
+                        return Some(Ok(self.token_since(c_at, TokenKind::Comment)));
+                    }
+                    continue;
+                }
+                // block comments nest, so `/* /* */ */` is a single comment
+                '/' if self.rest.starts_with('*') => {
+                    let mut depth = 1;
+                    let mut comment = self.rest[1..].char_indices();
+                    let end = loop {
+                        let Some((i, c)) = comment.next() else {
+                            break None;
+                        };
+                        let after = &self.rest[1 + i + c.len_utf8()..];
+                        if c == '/' && after.starts_with('*') {
+                            comment.next();
+                            depth += 1;
+                        } else if c == '*' && after.starts_with('/') {
+                            comment.next();
+                            depth -= 1;
+                            if depth == 0 {
+                                break Some(1 + i + 2);
+                            }
+                        }
+                    };
+
+                    let Some(end) = end else {
+                        let err = BlockCommentTerminationError {
+                            src: self.shared_source(),
+                            err_span: SourceSpan::from(c_at..c_at + 2),
+                        };
+                        self.advance(self.rest.len());
+                        return Some(Err(err.into()));
+                    };
+                    self.advance(end);
+                    if self.trivia {
+                        return Some(Ok(self.token_since(c_at, TokenKind::Comment)));
+                    }
+                    continue;
+                }
+                // comments take priority, only a `/` that opens none is left here
+                '/' => return Some(Ok(just(TokenKind::Slash))),
+                '\n' | '\r'
+                    if self.significant_newlines && (c == '\n' || self.rest.starts_with('\n')) =>
+                {
+                    let run = self
+                        .rest
+                        .find(|c: char| !is_whitespace(c))
+                        .unwrap_or(self.rest.len());
+                    let through_last_newline = self.rest[..run].rfind('\n').map_or(0, |i| i + 1);
+                    self.advance(through_last_newline);
+                    return Some(Ok(self.token_since(c_at, TokenKind::Newline)));
+                }
+                '\\' if self.line_continuations
+                    && (self.rest.starts_with('\n') || self.rest.starts_with("\r\n")) =>
+                {
+                    let line_break = if self.rest.starts_with('\n') { 1 } else { 2 };
+                    self.advance(line_break);
+                    if self.trivia {
+                        return Some(Ok(self.token_since(c_at, TokenKind::Whitespace)));
+                    }
+                    continue;
+                }
+                c if is_whitespace(c) => {
+                    if self.trivia {
+                        // a significant line break ends the whitespace
+                        let newlines = self.significant_newlines;
+                        let mut end = self
+                            .rest
+                            .find(|c: char| !is_whitespace(c) || (newlines && c == '\n'))
+                            .unwrap_or(self.rest.len());
+                        if newlines
+                            && self.rest[..end].ends_with('\r')
+                            && self.rest[end..].starts_with('\n')
+                        {
+                            end -= 1;
+                        }
+                        self.advance(end);
+                        return Some(Ok(self.token_since(c_at, TokenKind::Whitespace)));
+                    }
+                    continue;
+                }
+                c if c.is_ascii_punctuation() && is_unexpected_punctuation(self.rest) => {
+                    // report a run of unknown operator-like chars as one error
+                    let mut end = 0;
+                    while is_unexpected_punctuation(&self.rest[end..]) {
+                        end += 1;
+                    }
+                    let sequence = &c_onwards[..c.len_utf8() + end];
+                    self.advance(end);
+                    if self.unknown_tokens {
+                        return Some(Ok(self.token_since(c_at, TokenKind::Unknown)));
+                    }
+                    return Some(Err(UnexpectedSequenceError {
+                        src: self.shared_source(),
+                        sequence: sequence.to_string(),
+                        err_span: SourceSpan::from(c_at..self.byte),
+                    }
+                    .into()));
+                }
+                _ if self.unknown_tokens => return Some(Ok(just(TokenKind::Unknown))),
                 _ => {
                     return Some(Err(SingleTokenError {
-                        src: self.whole.to_string(),
+                        src: self.shared_source(),
                         token: c,
                         err_span: SourceSpan::from(self.byte - c.len_utf8()..self.byte),
                     }
@@ -75,44 +1402,333 @@ impl<'de> Iterator for Lexer<'de> {
 
             break match started {
                 Started::Ident => {
-                    let first_char_that_is_not_an_ident = c_onwards
-                        .find(|c| !matches!(c, 'a'..='z' | 'A'..='Z' | '_' | '0' ..='9'))
-                        .unwrap_or(c_onwards.len());
+                    let mut first_char_that_is_not_an_ident = ident_end(c_onwards);
+                    let mut is_path = false;
+                    // a dot only joins the next identifier, so `foo.` stays
+                    // an identifier followed by a `Dot`
+                    while let Some(after_dot) = c_onwards[first_char_that_is_not_an_ident..]
+                        .strip_prefix('.')
+                        .filter(|after_dot| self.dotted_paths && starts_ident(after_dot))
+                    {
+                        first_char_that_is_not_an_ident += 1 + ident_end(after_dot);
+                        is_path = true;
+                    }
 
                     let literal = &c_onwards[..first_char_that_is_not_an_ident];
                     let bytes_unaccounted_for = literal.len() - c.len_utf8();
-                    self.byte += bytes_unaccounted_for;
-                    self.rest = &self.rest[bytes_unaccounted_for..];
+                    self.advance(bytes_unaccounted_for);
+
+                    if let Some(max_len) = self
+                        .max_ident_len
+                        .filter(|&max| is_longer_than(literal, max))
+                    {
+                        return Some(Err(IdentLengthError {
+                            src: self.shared_source(),
+                            max_len,
+                            err_span: SourceSpan::from(c_at..self.byte),
+                        }
+                        .into()));
+                    }
 
-                    let kind = match literal {
-                        "task" => TokenKind::Task,
-                        _ => TokenKind::Ident,
+                    let kind = if is_path {
+                        TokenKind::Path
+                    } else {
+                        self.keywords
+                            .iter()
+                            .find(|(keyword, _)| *keyword == literal)
+                            .map_or(TokenKind::Ident, |(_, kind)| *kind)
                     };
 
-                    Some(Ok(Token {
-                        origin: literal,
-                        offset: c_at,
-                        kind,
-                    }))
+                    Some(Ok(self.token(literal, c_at, kind)))
                 }
-            };
-        }
-    }
-}
+                Started::Number => {
+                    let mut radix = match (c, self.rest.as_bytes().first()) {
+                        ('0', Some(b'x')) => 16,
+                        ('0', Some(b'o')) => 8,
+                        ('0', Some(b'b')) => 2,
+                        _ => 10,
+                    };
+                    // a prefix that runs on into letters, like `0bar` or
+                    // `0b1x`, starts a digit-led identifier instead
+                    if radix != 10
+                        && self.allow_leading_digit_idents
+                        && !is_prefixed_integer(&c_onwards[..ident_end(c_onwards)], radix)
+                    {
+                        radix = 10;
+                    }
+                    if radix != 10 {
+                        let digits = &c_onwards[2..];
+                        let digits_len = digits
+                            .find(|c: char| c != '_' && !c.is_digit(radix))
+                            .unwrap_or(digits.len());
+                        let digits = &digits[..digits_len];
+                        let literal = &c_onwards[..2 + digits_len];
+                        self.advance(literal.len() - c.len_utf8());
+
+                        if !digits.contains(|c: char| c.is_digit(radix)) {
+                            return Some(Err(MissingDigitsError {
+                                src: self.shared_source(),
+                                err_span: SourceSpan::from(c_at..c_at + 2),
+                            }
+                            .into()));
+                        }
+                        if let Some(separator) = misplaced_separator(literal, 2..literal.len()) {
+                            return Some(Err(DigitSeparatorError {
+                                src: self.shared_source(),
+                                err_span: SourceSpan::from(
+                                    c_at + separator.start..c_at + separator.end,
+                                ),
+                            }
+                            .into()));
+                        }
+
+                        return Some(Ok(self.token(literal, c_at, TokenKind::Integer)));
+                    }
+
+                    let mut kind = TokenKind::Integer;
+                    let mut end = digits_end(c_onwards);
+                    let mut bad_separator = misplaced_separator(c_onwards, 0..end);
+
+                    // a dot only belongs to the number if a digit follows it, so
+                    // that `3.foo` stays an integer followed by a dot
+                    let after = &c_onwards[end..];
+                    if after.starts_with('.')
+                        && after[1..].starts_with(|c: char| c.is_ascii_digit())
+                    {
+                        let fraction = end + 1;
+                        end = fraction + digits_end(&c_onwards[fraction..]);
+                        kind = TokenKind::Float;
+                        bad_separator =
+                            bad_separator.or_else(|| misplaced_separator(c_onwards, fraction..end));
+                    }
+
+                    let after = &c_onwards[end..];
+                    if let Some(exponent) = after.strip_prefix(['e', 'E']) {
+                        let exponent_digits = exponent.strip_prefix(['+', '-']).unwrap_or(exponent);
+                        if exponent_digits.starts_with(|c: char| c.is_ascii_digit()) {
+                            let exponent = end + after.len() - exponent_digits.len();
+                            end = exponent + digits_end(&c_onwards[exponent..]);
+                            kind = TokenKind::Float;
+                            bad_separator = bad_separator
+                                .or_else(|| misplaced_separator(c_onwards, exponent..end));
+                        }
+                    }
+
+                    // the unit has to be the whole word, `30sec` stays a number
+                    // followed by an identifier
+                    if self.unit_suffixes && kind == TokenKind::Integer {
+                        let after = &c_onwards[end..];
+                        let suffix = &after[..ident_end(after)];
+                        if let Some(unit) = unit_suffix(suffix) {
+                            end += suffix.len();
+                            kind = unit;
+                        }
+                    }
+
+                    // only a decimal integer runs on into an identifier, `1.5x`
+                    // stays a float followed by an identifier
+                    let after = &c_onwards[end..];
+                    if self.allow_leading_digit_idents
+                        && kind == TokenKind::Integer
+                        && ident_end(after) > 0
+                    {
+                        end += ident_end(after);
+                        kind = TokenKind::Ident;
+                        bad_separator = None;
+                    }
+
+                    let literal = &c_onwards[..end];
+                    let bytes_unaccounted_for = literal.len() - c.len_utf8();
+                    self.advance(bytes_unaccounted_for);
+
+                    if let Some(max_len) = self
+                        .max_ident_len
+                        .filter(|&max| kind == TokenKind::Ident && is_longer_than(literal, max))
+                    {
+                        return Some(Err(IdentLengthError {
+                            src: self.shared_source(),
+                            max_len,
+                            err_span: SourceSpan::from(c_at..self.byte),
+                        }
+                        .into()));
+                    }
+                    if let Some(separator) = bad_separator {
+                        return Some(Err(DigitSeparatorError {
+                            src: self.shared_source(),
+                            err_span: SourceSpan::from(
+                                c_at + separator.start..c_at + separator.end,
+                            ),
+                        }
+                        .into()));
+                    }
+
+                    Some(Ok(self.token(literal, c_at, kind)))
+                }
+                Started::String => {
+                    let mut invalid_escape = None;
+                    let mut chars = self.rest.char_indices();
+                    let end = loop {
+                        match chars.next() {
+                            Some((i, '"')) => break Some(i),
+                            Some((i, '\\')) => match chars.next() {
+                                Some((_, e)) if unescape_char(e).is_some() => {}
+                                Some((j, e)) => {
+                                    invalid_escape.get_or_insert(i..j + e.len_utf8());
+                                }
+                                None => break None,
+                            },
+                            Some(_) => {}
+                            None => break None,
+                        }
+                    };
+
+                    let Some(end) = end else {
+                        let err = StringTerminationError {
+                            src: self.shared_source(),
+                            err_span: SourceSpan::from(c_at..self.base + self.whole.len()),
+                        };
+                        self.advance(self.rest.len());
+                        return Some(Err(err.into()));
+                    };
+
+                    let literal = &c_onwards[..c.len_utf8() + end + 1];
+                    let string_start = self.byte;
+                    self.advance(end + 1);
+
+                    if let Some(escape) = invalid_escape {
+                        return Some(Err(InvalidEscapeError {
+                            src: self.shared_source(),
+                            err_span: SourceSpan::from(
+                                string_start + escape.start..string_start + escape.end,
+                            ),
+                        }
+                        .into()));
+                    }
+
+                    Some(Ok(self.token(literal, c_at, TokenKind::String)))
+                }
+                Started::Char => {
+                    let mut chars = self.rest.char_indices();
+                    let end = loop {
+                        match chars.next() {
+                            Some((i, '\'')) => break Some(i),
+                            Some((_, '\\')) => {
+                                chars.next();
+                            }
+                            Some((_, '\n')) | None => break None,
+                            Some(_) => {}
+                        }
+                    };
+
+                    // an unterminated literal ends at the end of its line
+                    let consumed = end.map_or_else(
+                        || self.rest.find('\n').unwrap_or(self.rest.len()),
+                        |end| end + 1,
+                    );
+                    let literal = &c_onwards[..c.len_utf8() + consumed];
+                    self.advance(consumed);
+
+                    let inner = end.map(|end| &literal[1..1 + end]);
+                    let mut inner_chars = inner.unwrap_or_default().chars();
+                    match (inner_chars.next(), inner_chars.next(), inner_chars.next()) {
+                        (Some('\\'), Some(e), None) if unescape_char(e).is_none() => {
+                            return Some(Err(InvalidEscapeError {
+                                src: self.shared_source(),
+                                err_span: SourceSpan::from(c_at + 1..c_at + 2 + e.len_utf8()),
+                            }
+                            .into()));
+                        }
+                        (Some('\\'), Some(_), None) | (Some(_), None, None) if end.is_some() => {}
+                        _ => {
+                            return Some(Err(CharLiteralError {
+                                src: self.shared_source(),
+                                err_span: SourceSpan::from(c_at..self.byte),
+                            }
+                            .into()));
+                        }
+                    }
+
+                    Some(Ok(self.token(literal, c_at, TokenKind::Char)))
+                }
+                Started::Heredoc { tag } => {
+                    // the body starts on the line after the opening `<<TAG`
+                    let body_at = self.rest.find('\n').expect("checked by heredoc_tag") + 1;
+                    let mut line_at = body_at;
+                    let end = loop {
+                        if line_at >= self.rest.len() {
+                            break None;
+                        }
+                        let line = self.rest[line_at..]
+                            .split_inclusive('\n')
+                            .next()
+                            .expect("not at the end");
+                        let content = line.trim_end_matches('\n').trim_end_matches('\r');
+                        if content.trim_start() == tag {
+                            break Some(line_at + content.len());
+                        }
+                        line_at += line.len();
+                    };
+
+                    let Some(end) = end else {
+                        let err = StringTerminationError {
+                            src: self.shared_source(),
+                            err_span: SourceSpan::from(c_at..self.base + self.whole.len()),
+                        };
+                        self.advance(self.rest.len());
+                        return Some(Err(err.into()));
+                    };
+
+                    let literal = &c_onwards[..c.len_utf8() + end];
+                    self.advance(end);
+
+                    Some(Ok(self.token(literal, c_at, TokenKind::String)))
+                }
+                Started::RawString { hashes } => {
+                    let opening = hashes + 1;
+                    let body = &self.rest[opening..];
+                    let end = body.match_indices('"').map(|(i, _)| i).find(|i| {
+                        body[i + 1..]
+                            .bytes()
+                            .take(hashes)
+                            .filter(|b| *b == b'#')
+                            .count()
+                            == hashes
+                    });
+
+                    let Some(end) = end else {
+                        let err = StringTerminationError {
+                            src: self.shared_source(),
+                            err_span: SourceSpan::from(c_at..c_at + c.len_utf8() + opening),
+                        };
+                        self.advance(self.rest.len());
+                        return Some(Err(err.into()));
+                    };
+
+                    let consumed = opening + end + 1 + hashes;
+                    let literal = &c_onwards[..c.len_utf8() + consumed];
+                    self.advance(consumed);
+
+                    Some(Ok(self.token(literal, c_at, TokenKind::String)))
+                }
+            };
+        }
+    }
+}
 
 #[cfg(test)]
 mod test {
-    use miette::Error;
+    use std::sync::Arc;
+
     use quickcheck::{Arbitrary, TestResult};
     use quickcheck_macros::quickcheck;
 
-    use crate::{error::SingleTokenError, Lexer, Token, TokenKind};
+    use crate::{error::LexError, Lexer, Token, TokenKind};
 
     macro_rules! test_token_kinds {
         ($name:ident, $input:literal, $res:expr) => {
             #[test]
             fn $name() {
-                let res: Vec<TokenKind> = $res;
+                let res: Vec<TokenKind> = $res.into_iter().chain([TokenKind::Eof]).collect();
                 let lexer = super::Lexer::new($input);
                 let got = lexer
                     .map(|t| t.unwrap())
@@ -123,6 +1739,27 @@ mod test {
         };
     }
 
+    // like `test_token_kinds!`, but checks the `(kind, offset, origin)` of
+    // each token
+    macro_rules! test_tokens {
+        ($name:ident, $input:literal, $res:expr) => {
+            #[test]
+            fn $name() {
+                let input: &str = $input;
+                let res: Vec<(TokenKind, usize, &str)> = $res
+                    .into_iter()
+                    .chain([(TokenKind::Eof, input.len(), "")])
+                    .collect();
+                let lexer = super::Lexer::new(input);
+                let got = lexer
+                    .map(|t| t.unwrap())
+                    .map(|t| (t.kind, t.offset, t.origin))
+                    .collect::<Vec<_>>();
+                assert_eq!(res, got);
+            }
+        };
+    }
+
     test_token_kinds!(test_empty, "", vec![]);
     test_token_kinds!(
         test_braces,
@@ -134,14 +1771,14 @@ mod test {
         "\n\n{\n}",
         vec![TokenKind::LeftBrace, TokenKind::RightBrace]
     );
-    test_token_kinds!(
+    test_tokens!(
         test_empty_task,
         "task foo {}",
         vec![
-            TokenKind::Task,
-            TokenKind::Ident,
-            TokenKind::LeftBrace,
-            TokenKind::RightBrace
+            (TokenKind::Task, 0, "task"),
+            (TokenKind::Ident, 5, "foo"),
+            (TokenKind::LeftBrace, 9, "{"),
+            (TokenKind::RightBrace, 10, "}"),
         ]
     );
     test_token_kinds!(
@@ -164,14 +1801,14 @@ mod test {
             TokenKind::RightBrace
         ]
     );
-    test_token_kinds!(
+    test_tokens!(
         test_empty_task_with_new_lines,
         "task foo\n{\n}",
         vec![
-            TokenKind::Task,
-            TokenKind::Ident,
-            TokenKind::LeftBrace,
-            TokenKind::RightBrace
+            (TokenKind::Task, 0, "task"),
+            (TokenKind::Ident, 5, "foo"),
+            (TokenKind::LeftBrace, 9, "{"),
+            (TokenKind::RightBrace, 11, "}"),
         ]
     );
 
@@ -189,77 +1826,2277 @@ mod test {
         ]
     );
 
-    #[derive(Clone, Debug)]
-    struct PropIdent(String);
+    test_token_kinds!(
+        test_string_in_task,
+        "task foo { \"bar\" }",
+        vec![
+            TokenKind::Task,
+            TokenKind::Ident,
+            TokenKind::LeftBrace,
+            TokenKind::String,
+            TokenKind::RightBrace
+        ]
+    );
+    test_token_kinds!(test_empty_string, "\"\"", vec![TokenKind::String]);
 
-    impl Arbitrary for PropIdent {
-        fn arbitrary(g: &mut quickcheck::Gen) -> Self {
-            let allowed: Vec<char> = (0..0xff_u8)
-                .filter(|i| (*i as char).is_alphanumeric() && (*i as char).is_ascii())
-                .map(|i| i as char)
-                .collect();
-            let len = g.size().min(200);
-            let mut s = (0..len)
-                .map(|_| g.choose(&allowed).unwrap())
-                .collect::<String>();
-            if s.chars().next().unwrap().is_numeric() {
-                s = format!("_{s}");
-            }
-            // println!("{s}");
-            Self(s)
-        }
+    test_token_kinds!(test_integer, "42", vec![TokenKind::Integer]);
+    test_token_kinds!(test_zero, "0", vec![TokenKind::Integer]);
+    test_token_kinds!(test_leading_zeros, "007", vec![TokenKind::Integer]);
+    test_token_kinds!(
+        test_integer_followed_by_ident,
+        "3ab",
+        vec![TokenKind::Integer, TokenKind::Ident]
+    );
+    test_token_kinds!(
+        test_integer_with_separators,
+        "1_000",
+        vec![TokenKind::Integer]
+    );
+    test_token_kinds!(
+        test_leading_underscore_is_an_ident,
+        "_1000 1_000",
+        vec![TokenKind::Ident, TokenKind::Integer]
+    );
+    test_token_kinds!(test_float, "3.14", vec![TokenKind::Float]);
+    test_token_kinds!(test_float_below_one, "0.5", vec![TokenKind::Float]);
+    test_token_kinds!(test_float_with_exponent, "1e9", vec![TokenKind::Float]);
+    test_token_kinds!(
+        test_float_with_fraction_and_negative_exponent,
+        "2.5e-3",
+        vec![TokenKind::Float]
+    );
+    test_token_kinds!(
+        test_exponent_without_digits_is_an_ident,
+        "1ea",
+        vec![TokenKind::Integer, TokenKind::Ident]
+    );
+    test_token_kinds!(
+        test_integer_in_task,
+        "task foo { retries 3 }",
+        vec![
+            TokenKind::Task,
+            TokenKind::Ident,
+            TokenKind::LeftBrace,
+            TokenKind::Ident,
+            TokenKind::Integer,
+            TokenKind::RightBrace
+        ]
+    );
 
-        fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
-            let len = self.0.len();
-            let s = self.0.to_owned();
-            Box::new((1..len).map(move |i| Self(s[..s.len() - i].to_owned())))
-        }
-    }
+    test_token_kinds!(
+        test_trailing_line_comment,
+        "task foo {} # trailing",
+        vec![
+            TokenKind::Task,
+            TokenKind::Ident,
+            TokenKind::LeftBrace,
+            TokenKind::RightBrace
+        ]
+    );
+    test_token_kinds!(
+        test_line_comment_on_its_own_line,
+        "# leading { task\ntask foo {\n  # inside }\n}",
+        vec![
+            TokenKind::Task,
+            TokenKind::Ident,
+            TokenKind::LeftBrace,
+            TokenKind::RightBrace
+        ]
+    );
+    test_token_kinds!(test_only_a_comment, "# task foo {}", vec![]);
+    test_token_kinds!(
+        test_double_slash_line_comment,
+        "a // comment { \"\nb",
+        vec![TokenKind::Ident, TokenKind::Ident]
+    );
+    test_token_kinds!(
+        test_division_is_no_comment,
+        "a / b",
+        vec![TokenKind::Ident, TokenKind::Slash, TokenKind::Ident]
+    );
+    test_token_kinds!(
+        test_block_comment_between_idents,
+        "a /* block */ b",
+        vec![TokenKind::Ident, TokenKind::Ident]
+    );
+    test_token_kinds!(
+        test_double_slash_after_a_slash,
+        "a ///c\n/ //* not a block",
+        vec![TokenKind::Ident, TokenKind::Slash]
+    );
+    test_token_kinds!(
+        test_block_comment,
+        "task /* a comment */ foo {}",
+        vec![
+            TokenKind::Task,
+            TokenKind::Ident,
+            TokenKind::LeftBrace,
+            TokenKind::RightBrace
+        ]
+    );
+    test_token_kinds!(
+        test_multi_line_block_comment,
+        "/*\ntask foo {\n}\n*/task bar {}",
+        vec![
+            TokenKind::Task,
+            TokenKind::Ident,
+            TokenKind::LeftBrace,
+            TokenKind::RightBrace
+        ]
+    );
+    test_token_kinds!(
+        test_nested_block_comment,
+        "/* outer /* inner */ still outer */ foo",
+        vec![TokenKind::Ident]
+    );
+    test_token_kinds!(
+        test_block_comment_stars,
+        "/***/ foo /**/",
+        vec![TokenKind::Ident]
+    );
 
-    #[quickcheck]
-    fn prop_test_task_ident(ident: PropIdent) -> TestResult {
-        let ident = ident.0;
-        let input = format!("task {} {{}}", ident);
-        let tokens = super::Lexer::new(&input);
-        let tokens = tokens
-            .map(|t| t.map(|t| t.kind))
-            .collect::<Result<Vec<_>, _>>()
-            .unwrap();
-        if vec![
+    test_token_kinds!(
+        test_unicode_ident,
+        "task café {}",
+        vec![
             TokenKind::Task,
             TokenKind::Ident,
             TokenKind::LeftBrace,
-            TokenKind::RightBrace,
-        ] == tokens
-        {
-            return TestResult::passed();
-        }
+            TokenKind::RightBrace
+        ]
+    );
 
-        TestResult::failed()
-    }
+    test_token_kinds!(
+        test_colon,
+        "key: value",
+        vec![TokenKind::Ident, TokenKind::Colon, TokenKind::Ident]
+    );
+    test_token_kinds!(test_leading_colon, ":", vec![TokenKind::Colon]);
+    test_token_kinds!(
+        test_colons_are_single_chars,
+        "a::b",
+        vec![
+            TokenKind::Ident,
+            TokenKind::Colon,
+            TokenKind::Colon,
+            TokenKind::Ident
+        ]
+    );
+    test_token_kinds!(
+        test_key_value_in_task,
+        "task foo { timeout: 30 }",
+        vec![
+            TokenKind::Task,
+            TokenKind::Ident,
+            TokenKind::LeftBrace,
+            TokenKind::Ident,
+            TokenKind::Colon,
+            TokenKind::Integer,
+            TokenKind::RightBrace
+        ]
+    );
 
-    #[test]
-    fn it_fails_with_an_error_when_hitting_an_invalid_char() {
-        let lexer = Lexer::new("  #");
-        let res: Result<Vec<Token>, Error> = lexer.collect();
-        let Err(e) = res else {
-            panic!("should have failed");
-        };
-        let e = e.downcast_ref::<SingleTokenError>().unwrap();
-        assert_eq!(2, e.err_span.offset());
-        assert_eq!(1, e.err_span.len());
-        assert_eq!('#', e.token);
-        assert_eq!(1, e.line());
+    test_token_kinds!(
+        test_separators,
+        "a, b; c.d",
+        vec![
+            TokenKind::Ident,
+            TokenKind::Comma,
+            TokenKind::Ident,
+            TokenKind::Semicolon,
+            TokenKind::Ident,
+            TokenKind::Dot,
+            TokenKind::Ident
+        ]
+    );
+    test_token_kinds!(
+        test_dot_before_digit_outside_number,
+        ".5",
+        vec![TokenKind::Dot, TokenKind::Integer]
+    );
 
-        let lexer = Lexer::new("task foo {}\n$");
-        let res: Result<Vec<Token>, Error> = lexer.collect();
-        let Err(e) = res else {
-            panic!("should have failed");
-        };
-        let e = e.downcast_ref::<SingleTokenError>().unwrap();
-        assert_eq!(12, e.err_span.offset());
-        assert_eq!(1, e.err_span.len());
-        assert_eq!('$', e.token);
-        assert_eq!(2, e.line());
+    test_token_kinds!(
+        test_parens_and_brackets,
+        "foo(a)[b]",
+        vec![
+            TokenKind::Ident,
+            TokenKind::LeftParen,
+            TokenKind::Ident,
+            TokenKind::RightParen,
+            TokenKind::LeftBracket,
+            TokenKind::Ident,
+            TokenKind::RightBracket
+        ]
+    );
+    test_token_kinds!(
+        test_nested_parens_and_brackets,
+        "([])",
+        vec![
+            TokenKind::LeftParen,
+            TokenKind::LeftBracket,
+            TokenKind::RightBracket,
+            TokenKind::RightParen
+        ]
+    );
+
+    test_token_kinds!(
+        test_equals,
+        "x = y",
+        vec![TokenKind::Ident, TokenKind::Equals, TokenKind::Ident]
+    );
+    test_token_kinds!(
+        test_double_equals_is_one_token,
+        "x == y",
+        vec![TokenKind::Ident, TokenKind::EqualEqual, TokenKind::Ident]
+    );
+    test_token_kinds!(
+        test_assignment_of_a_string,
+        "name = \"build\"",
+        vec![TokenKind::Ident, TokenKind::Equals, TokenKind::String]
+    );
+
+    test_token_kinds!(
+        test_keywords,
+        "task if else for in return true false",
+        vec![
+            TokenKind::Task,
+            TokenKind::If,
+            TokenKind::Else,
+            TokenKind::For,
+            TokenKind::In,
+            TokenKind::Return,
+            TokenKind::True,
+            TokenKind::False
+        ]
+    );
+    test_token_kinds!(
+        test_keywords_as_part_of_identifiers,
+        "iffy taskish elsewhere format inner returns trueish falsehood _if",
+        vec![TokenKind::Ident; 9]
+    );
+    test_token_kinds!(test_true, "true", vec![TokenKind::True]);
+    test_token_kinds!(test_false, "false", vec![TokenKind::False]);
+    test_token_kinds!(
+        test_booleans_as_values,
+        "cache: true, force = false",
+        vec![
+            TokenKind::Ident,
+            TokenKind::Colon,
+            TokenKind::True,
+            TokenKind::Comma,
+            TokenKind::Ident,
+            TokenKind::Equals,
+            TokenKind::False
+        ]
+    );
+    test_token_kinds!(
+        test_boolean_near_misses,
+        "truely falsey True FALSE tru",
+        vec![TokenKind::Ident; 5]
+    );
+    test_token_kinds!(
+        test_keywords_are_case_sensitive,
+        "If TASK",
+        vec![TokenKind::Ident, TokenKind::Ident]
+    );
+
+    test_token_kinds!(test_raw_string, "r\"a\\nb\"", vec![TokenKind::String]);
+    test_token_kinds!(
+        test_r_is_still_an_identifier,
+        "r raw r# a comment\nr",
+        vec![
+            TokenKind::Ident,
+            TokenKind::Ident,
+            TokenKind::Ident,
+            TokenKind::Ident
+        ]
+    );
+
+    test_token_kinds!(
+        test_minus_between_operands,
+        "x - 5",
+        vec![TokenKind::Ident, TokenKind::Minus, TokenKind::Integer]
+    );
+    test_token_kinds!(
+        test_negative_number_is_minus_and_integer,
+        "-5",
+        vec![TokenKind::Minus, TokenKind::Integer]
+    );
+    test_token_kinds!(
+        test_negative_float_is_minus_and_float,
+        "x: -2.5e-3",
+        vec![
+            TokenKind::Ident,
+            TokenKind::Colon,
+            TokenKind::Minus,
+            TokenKind::Float
+        ]
+    );
+
+    test_token_kinds!(
+        test_arithmetic_operators,
+        "a + b * c / d % e",
+        vec![
+            TokenKind::Ident,
+            TokenKind::Plus,
+            TokenKind::Ident,
+            TokenKind::Star,
+            TokenKind::Ident,
+            TokenKind::Slash,
+            TokenKind::Ident,
+            TokenKind::Percent,
+            TokenKind::Ident
+        ]
+    );
+    test_token_kinds!(
+        test_slash_next_to_comments,
+        "a/ /* b */ /c */",
+        vec![
+            TokenKind::Ident,
+            TokenKind::Slash,
+            TokenKind::Slash,
+            TokenKind::Ident,
+            TokenKind::Star,
+            TokenKind::Slash
+        ]
+    );
+
+    test_token_kinds!(
+        test_comparison_operators,
+        "a == b != c > d >= e < f <= g",
+        vec![
+            TokenKind::Ident,
+            TokenKind::EqualEqual,
+            TokenKind::Ident,
+            TokenKind::NotEqual,
+            TokenKind::Ident,
+            TokenKind::Greater,
+            TokenKind::Ident,
+            TokenKind::GreaterEqual,
+            TokenKind::Ident,
+            TokenKind::Less,
+            TokenKind::Ident,
+            TokenKind::LessEqual,
+            TokenKind::Ident
+        ]
+    );
+    test_token_kinds!(
+        test_logical_operators,
+        "!a && b || !c",
+        vec![
+            TokenKind::Bang,
+            TokenKind::Ident,
+            TokenKind::AmpAmp,
+            TokenKind::Ident,
+            TokenKind::PipePipe,
+            TokenKind::Bang,
+            TokenKind::Ident
+        ]
+    );
+    test_token_kinds!(
+        test_two_char_operators_before_their_prefix,
+        "if x >= 3 { x > = 3 }",
+        vec![
+            TokenKind::If,
+            TokenKind::Ident,
+            TokenKind::GreaterEqual,
+            TokenKind::Integer,
+            TokenKind::LeftBrace,
+            TokenKind::Ident,
+            TokenKind::Greater,
+            TokenKind::Equals,
+            TokenKind::Integer,
+            TokenKind::RightBrace
+        ]
+    );
+    test_token_kinds!(
+        test_operators_without_spaces,
+        "x>=3>2===!=!",
+        vec![
+            TokenKind::Ident,
+            TokenKind::GreaterEqual,
+            TokenKind::Integer,
+            TokenKind::Greater,
+            TokenKind::Integer,
+            TokenKind::EqualEqual,
+            TokenKind::Equals,
+            TokenKind::NotEqual,
+            TokenKind::Bang
+        ]
+    );
+    test_token_kinds!(
+        test_less_and_heredoc,
+        "a < b << c <<EOF\nx\nEOF\n",
+        vec![
+            TokenKind::Ident,
+            TokenKind::Less,
+            TokenKind::Ident,
+            TokenKind::Less,
+            TokenKind::Less,
+            TokenKind::Ident,
+            TokenKind::String
+        ]
+    );
+
+    #[test]
+    fn it_fails_on_a_single_ampersand_or_pipe() {
+        let (_, errors) = Lexer::new("a & b | c").lex_all();
+        let tokens = errors
+            .iter()
+            .map(|e| match e {
+                LexError::UnexpectedChar(e) => e.token,
+                _ => panic!("should have been UnexpectedChar"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(vec!['&', '|'], tokens);
+    }
+
+    test_token_kinds!(
+        test_arrow_between_tasks,
+        "task a -> b {}",
+        vec![
+            TokenKind::Task,
+            TokenKind::Ident,
+            TokenKind::Arrow,
+            TokenKind::Ident,
+            TokenKind::LeftBrace,
+            TokenKind::RightBrace
+        ]
+    );
+    test_token_kinds!(
+        test_arrow_without_spaces,
+        "a->b",
+        vec![TokenKind::Ident, TokenKind::Arrow, TokenKind::Ident]
+    );
+    test_token_kinds!(test_bare_minus, "-", vec![TokenKind::Minus]);
+    test_token_kinds!(
+        test_double_dash_before_arguments,
+        "task build -- arg",
+        vec![
+            TokenKind::Task,
+            TokenKind::Ident,
+            TokenKind::DashDash,
+            TokenKind::Ident
+        ]
+    );
+    test_token_kinds!(test_bare_double_dash, "--", vec![TokenKind::DashDash]);
+    test_token_kinds!(
+        test_double_dash_before_greater,
+        "-->",
+        vec![TokenKind::DashDash, TokenKind::Greater]
+    );
+    test_token_kinds!(
+        test_minus_after_double_dash,
+        "---",
+        vec![TokenKind::DashDash, TokenKind::Minus]
+    );
+    test_token_kinds!(
+        test_arrow_before_double_dash,
+        "->-->",
+        vec![TokenKind::Arrow, TokenKind::DashDash, TokenKind::Greater]
+    );
+
+    test_token_kinds!(
+        test_attribute,
+        "@foo",
+        vec![TokenKind::At, TokenKind::Ident]
+    );
+    test_token_kinds!(
+        test_attribute_before_task,
+        "@cached task foo {}",
+        vec![
+            TokenKind::At,
+            TokenKind::Ident,
+            TokenKind::Task,
+            TokenKind::Ident,
+            TokenKind::LeftBrace,
+            TokenKind::RightBrace
+        ]
+    );
+
+    test_token_kinds!(
+        test_variable_in_arguments,
+        "run($HOME, $_x1)",
+        vec![
+            TokenKind::Ident,
+            TokenKind::LeftParen,
+            TokenKind::Variable,
+            TokenKind::Comma,
+            TokenKind::Variable,
+            TokenKind::RightParen
+        ]
+    );
+
+    #[test]
+    fn it_lexes_a_variable_including_its_dollar() {
+        let mut lexer = Lexer::new("$HOME $réb");
+        let home = lexer.next().unwrap().unwrap();
+        assert_eq!(
+            (TokenKind::Variable, "$HOME", 0),
+            (home.kind, home.origin, home.offset)
+        );
+        let reb = lexer.next().unwrap().unwrap();
+        assert_eq!(
+            (TokenKind::Variable, "$réb", 6),
+            (reb.kind, reb.origin, reb.offset)
+        );
+        assert_eq!("variable `$HOME`", home.to_string());
+    }
+
+    #[test]
+    fn it_fails_on_a_dollar_before_a_brace() {
+        let (tokens, errors) = Lexer::new("${is a separate concern}").lex_all();
+        let [LexError::UnexpectedChar(e)] = &errors[..] else {
+            panic!("should have been a single UnexpectedChar");
+        };
+        assert_eq!('$', e.token);
+        assert_eq!((0, 1), (e.err_span.offset(), e.err_span.len()));
+        let kinds = tokens.iter().map(|t| t.kind).collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                TokenKind::LeftBrace,
+                TokenKind::Ident,
+                TokenKind::Ident,
+                TokenKind::Ident,
+                TokenKind::Ident,
+                TokenKind::RightBrace,
+                TokenKind::Eof
+            ],
+            kinds
+        );
+    }
+
+    #[test]
+    fn it_fails_on_a_bare_dollar() {
+        let res = crate::tokenize("foo $ bar");
+        let Err(LexError::UnexpectedChar(e)) = res else {
+            panic!("should have failed");
+        };
+        assert_eq!('$', e.token);
+        assert_eq!((4, 1), (e.err_span.offset(), e.err_span.len()));
+    }
+
+    #[test]
+    fn it_keeps_a_variable_out_of_a_run_of_unknown_punctuation() {
+        let (tokens, errors) = Lexer::new("?$HOME").lex_all();
+        let [LexError::UnexpectedChar(e)] = &errors[..] else {
+            panic!("should have been a single UnexpectedChar");
+        };
+        assert_eq!('?', e.token);
+        assert_eq!(TokenKind::Variable, tokens[0].kind);
+    }
+
+    #[derive(Clone, Debug)]
+    struct PropIdent(String);
+
+    impl Arbitrary for PropIdent {
+        fn arbitrary(g: &mut quickcheck::Gen) -> Self {
+            let allowed: Vec<char> = (0..0xff_u8)
+                .filter(|i| (*i as char).is_alphanumeric() && (*i as char).is_ascii())
+                .map(|i| i as char)
+                .collect();
+            let len = g.size().min(200);
+            let mut s = (0..len)
+                .map(|_| g.choose(&allowed).unwrap())
+                .collect::<String>();
+            if s.chars().next().unwrap().is_numeric() {
+                s = format!("_{s}");
+            }
+            // println!("{s}");
+            Self(s)
+        }
+
+        fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+            let len = self.0.len();
+            let s = self.0.to_owned();
+            Box::new((1..len).map(move |i| Self(s[..s.len() - i].to_owned())))
+        }
+    }
+
+    #[quickcheck]
+    fn prop_ident_fast_path_agrees_with_chars(ident: PropIdent, rest: String) -> bool {
+        let input = format!("{}{rest}", ident.0);
+        super::ident_end(&input) == super::ident_end_by_chars(&input)
+            && super::ident_end(&rest) == super::ident_end_by_chars(&rest)
+    }
+
+    #[test]
+    fn it_falls_back_to_chars_after_ascii_ident_bytes() {
+        assert_eq!(3, super::ident_end("foo bar"));
+        assert_eq!(5, super::ident_end("fooé bar"));
+        assert_eq!(6, super::ident_end("f_9λx+"));
+        assert_eq!(3, super::ident_end("foo🦀"));
+        assert_eq!(0, super::ident_end("-foo"));
+    }
+
+    #[quickcheck]
+    fn prop_test_task_ident(ident: PropIdent) -> TestResult {
+        let ident = ident.0;
+        let input = format!("task {} {{}}", ident);
+        let tokens = super::Lexer::new(&input);
+        let tokens = tokens
+            .map(|t| t.map(|t| t.kind))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        if vec![
+            TokenKind::Task,
+            TokenKind::Ident,
+            TokenKind::LeftBrace,
+            TokenKind::RightBrace,
+            TokenKind::Eof,
+        ] == tokens
+        {
+            return TestResult::passed();
+        }
+
+        TestResult::failed()
+    }
+
+    // source made of the chars that start or end tokens, to reach every branch
+    #[derive(Clone, Debug)]
+    struct PropSource(String);
+
+    impl Arbitrary for PropSource {
+        fn arbitrary(g: &mut quickcheck::Gen) -> Self {
+            let pieces = [
+                "task", "foo", "r", "EOF", "λ", "🦀", "0", "7", "_", "e", "-", "+", ".", "\"",
+                "\\", "'", "#", "/", "*", "<", "{", "}", ":", "=", "?", " ", "\n", "\r", "\t",
+            ];
+            let len = g.size();
+            Self(
+                (0..len)
+                    .map(|_| *g.choose(&pieces).unwrap())
+                    .collect::<String>(),
+            )
+        }
+
+        fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+            Box::new(self.0.shrink().map(Self))
+        }
+    }
+
+    // a lexer that stops consuming input would never reach the end
+    fn lexes_to_the_end(mut lexer: Lexer) -> bool {
+        let max_steps = lexer.source().len() + 1;
+        let mut steps = 0;
+        let mut byte = lexer.byte_offset();
+        while let Some(token) = lexer.next() {
+            steps += 1;
+            let is_eof = matches!(
+                token,
+                Ok(Token {
+                    kind: TokenKind::Eof,
+                    ..
+                })
+            );
+            if steps > max_steps || (!is_eof && lexer.byte_offset() <= byte) {
+                return false;
+            }
+            byte = lexer.byte_offset();
+        }
+        byte == lexer.source().len()
+    }
+
+    #[quickcheck]
+    fn prop_lexer_always_terminates(input: String) -> bool {
+        lexes_to_the_end(Lexer::new(&input)) && lexes_to_the_end(Lexer::new(&input).with_trivia())
+    }
+
+    #[quickcheck]
+    fn prop_lexer_always_terminates_on_token_soup(input: PropSource) -> bool {
+        let input = input.0;
+        lexes_to_the_end(Lexer::new(&input)) && lexes_to_the_end(Lexer::new(&input).with_trivia())
+    }
+
+    // token starts glued to multi-byte chars, so every slice lands next to one
+    #[derive(Clone, Debug)]
+    struct PropMultiByteSource(String);
+
+    impl Arbitrary for PropMultiByteSource {
+        fn arbitrary(g: &mut quickcheck::Gen) -> Self {
+            let tokens = [
+                "task", "r", "r#\"", "\"#", "<<EOF\n", "EOF", "0x", "0b_", "1.", "1e", "1_", "'",
+                "\"", "\\", "/*", "*/", "#", "?", "-", "=", " ", "\n",
+            ];
+            let wide = ["é", "λ", "ß", "中", "🦀", "\u{301}", "\u{200b}", "\u{feff}"];
+            let len = g.size();
+            Self(
+                (0..len)
+                    .flat_map(|_| [*g.choose(&tokens).unwrap(), *g.choose(&wide).unwrap()])
+                    .collect::<String>(),
+            )
+        }
+
+        fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+            Box::new(self.0.shrink().map(Self))
+        }
+    }
+
+    // every token and error span of `lexer` starts and ends on a char boundary
+    fn slices_at_char_boundaries(lexer: Lexer) -> bool {
+        let source = lexer.source();
+        let on_boundaries = |at: usize, len: usize| {
+            source.is_char_boundary(at) && source.is_char_boundary(at + len)
+        };
+        lexer.into_iter().all(|token| match token {
+            Ok(token) => {
+                let _ = token.unescaped();
+                on_boundaries(token.offset, token.origin.len())
+            }
+            Err(e) => {
+                let span = e.span();
+                on_boundaries(span.start, span.len)
+            }
+        })
+    }
+
+    #[quickcheck]
+    fn prop_lexer_only_slices_at_char_boundaries(input: PropMultiByteSource) -> bool {
+        let input = input.0;
+        slices_at_char_boundaries(Lexer::new(&input))
+            && slices_at_char_boundaries(Lexer::new(&input).with_trivia())
+    }
+
+    // a source made of whole tokens and trivia, which mostly lexes without errors
+    #[derive(Clone, Debug)]
+    struct PropWellFormedSource(String);
+
+    impl Arbitrary for PropWellFormedSource {
+        fn arbitrary(g: &mut quickcheck::Gen) -> Self {
+            let pieces = [
+                "task",
+                "foo",
+                "λ",
+                "{",
+                "}",
+                "(",
+                ")",
+                ",",
+                "=",
+                "==",
+                "->",
+                "--",
+                "-",
+                "@",
+                "$HOME",
+                "42",
+                "0x_ff",
+                "1.5",
+                "\"str\"",
+                "\"🦀\\n\"",
+                "'c'",
+                "r#\"raw\"#",
+                "<<EOF\nbody\nEOF\n",
+                "# comment\n",
+                "// comment\n",
+                "/* block */",
+                " ",
+                "  ",
+                "\n",
+                "\r\n",
+                "\t",
+            ];
+            let len = g.size();
+            Self(
+                (0..len)
+                    .map(|_| *g.choose(&pieces).unwrap())
+                    .collect::<String>(),
+            )
+        }
+
+        fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+            Box::new(self.0.shrink().map(Self))
+        }
+    }
+
+    // tokens that drop or repeat bytes would not add up to the source
+    #[quickcheck]
+    fn prop_trivia_tokens_add_up_to_the_source(input: PropWellFormedSource) -> TestResult {
+        let input = input.0;
+        let Ok(tokens) = Lexer::new(&input)
+            .with_trivia()
+            .collect::<Result<Vec<_>, _>>()
+        else {
+            return TestResult::discard();
+        };
+        let origins = tokens.iter().map(|t| t.origin).collect::<String>();
+        TestResult::from_bool(origins == input)
+    }
+
+    #[test]
+    fn it_fails_with_an_error_when_hitting_an_invalid_char() {
+        let lexer = Lexer::new("  ?");
+        let res: Result<Vec<Token>, LexError> = lexer.collect();
+        let Err(LexError::UnexpectedChar(e)) = res else {
+            panic!("should have failed");
+        };
+        assert_eq!(2, e.err_span.offset());
+        assert_eq!(1, e.err_span.len());
+        assert_eq!('?', e.token);
+        assert_eq!(1, e.line());
+
+        let lexer = Lexer::new("task foo {}\n$");
+        let res: Result<Vec<Token>, LexError> = lexer.collect();
+        let Err(LexError::UnexpectedChar(e)) = res else {
+            panic!("should have failed");
+        };
+        assert_eq!(12, e.err_span.offset());
+        assert_eq!(1, e.err_span.len());
+        assert_eq!('$', e.token);
+        assert_eq!(2, e.line());
+    }
+
+    #[test]
+    fn it_keeps_the_quotes_in_the_string_origin() {
+        let lexer = Lexer::new("task foo { \"bar\" }");
+        let tokens: Vec<Token> = lexer.collect::<Result<_, _>>().unwrap();
+        let string = tokens[3];
+        assert_eq!(TokenKind::String, string.kind);
+        assert_eq!("\"bar\"", string.origin);
+        assert_eq!(11, string.offset);
+    }
+
+    #[test]
+    fn it_fails_with_an_error_on_an_unterminated_string() {
+        let lexer = Lexer::new("task foo { \"bar }");
+        let res: Result<Vec<Token>, LexError> = lexer.collect();
+        let Err(LexError::UnterminatedString(e)) = res else {
+            panic!("should have failed");
+        };
+        assert_eq!(11, e.err_span.offset());
+        assert_eq!(6, e.err_span.len());
+    }
+
+    fn unescape(input: &str) -> String {
+        let mut lexer = Lexer::new(input);
+        let token = lexer.next().unwrap().unwrap();
+        assert_eq!(TokenKind::String, token.kind);
+        token.unescaped().unwrap().into_owned()
+    }
+
+    #[test]
+    fn it_unescapes_string_literals() {
+        assert_eq!("a\nb", unescape(r#""a\nb""#));
+        assert_eq!("quote\"inside", unescape(r#""quote\"inside""#));
+        assert_eq!("tab\tback\\slash", unescape(r#""tab\tback\\slash""#));
+        assert_eq!("plain", unescape(r#""plain""#));
+    }
+
+    #[test]
+    fn it_strips_the_delimiters_from_the_token_text() {
+        let texts =
+            crate::tokenize("task foo $HOME \"a\\nb\" 'x' r#\"raw \"q\"\"# <<EOF\n  body\n  EOF\n")
+                .unwrap()
+                .iter()
+                .map(|t| t.text())
+                .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                "task",
+                "foo",
+                "HOME",
+                "a\\nb",
+                "x",
+                "raw \"q\"",
+                "  body\n",
+                ""
+            ],
+            texts
+        );
+    }
+
+    #[test]
+    fn it_keeps_the_raw_escapes_in_the_string_origin() {
+        let lexer = Lexer::new(r#"task foo { "quote\"inside" }"#);
+        let kinds = lexer
+            .map(|t| t.map(|t| (t.kind, t.origin)))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!((TokenKind::String, r#""quote\"inside""#), kinds[3]);
+        assert_eq!((TokenKind::RightBrace, "}"), kinds[4]);
+    }
+
+    #[test]
+    fn it_fails_with_an_error_on_an_invalid_escape() {
+        let lexer = Lexer::new(r#"task foo { "\q" }"#);
+        let res: Result<Vec<Token>, LexError> = lexer.collect();
+        let Err(LexError::InvalidEscape(e)) = res else {
+            panic!("should have failed");
+        };
+        assert_eq!(12, e.err_span.offset());
+        assert_eq!(2, e.err_span.len());
+
+        let whole = r#"x "\q""#;
+        let token = Token {
+            origin: &whole[2..],
+            offset: 2,
+            kind: TokenKind::String,
+            whole,
+            line: 1,
+            tab_width: 1,
+            merged: false,
+        };
+        let e = token.unescaped().unwrap_err();
+        let LexError::InvalidEscape(e) = e else {
+            panic!("should have been InvalidEscape");
+        };
+        assert_eq!(3, e.err_span.offset());
+        assert_eq!(2, e.err_span.len());
+    }
+
+    #[test]
+    fn it_lexes_integers_as_their_digits() {
+        let lexer = Lexer::new("3ab 007");
+        let tokens = lexer
+            .map(|t| t.map(|t| (t.kind, t.origin, t.offset)))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            vec![
+                (TokenKind::Integer, "3", 0),
+                (TokenKind::Ident, "ab", 1),
+                (TokenKind::Integer, "007", 4),
+                (TokenKind::Eof, "", 7)
+            ],
+            tokens
+        );
+    }
+
+    #[test]
+    fn it_fails_with_an_error_on_a_misplaced_digit_separator() {
+        let lexer = Lexer::new("retries 10_");
+        let res: Result<Vec<Token>, LexError> = lexer.collect();
+        let Err(LexError::MisplacedDigitSeparator(e)) = res else {
+            panic!("should have failed");
+        };
+        assert_eq!(10, e.err_span.offset());
+        assert_eq!(1, e.err_span.len());
+
+        let lexer = Lexer::new("retries 1__0");
+        let res: Result<Vec<Token>, LexError> = lexer.collect();
+        let Err(LexError::MisplacedDigitSeparator(e)) = res else {
+            panic!("should have failed");
+        };
+        assert_eq!(9, e.err_span.offset());
+        assert_eq!(2, e.err_span.len());
+    }
+
+    #[test]
+    fn it_does_not_consume_a_dot_without_a_following_digit() {
+        let tokens = crate::tokenize("3.foo").unwrap();
+        let tokens = tokens
+            .iter()
+            .map(|t| (t.kind, t.origin))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                (TokenKind::Integer, "3"),
+                (TokenKind::Dot, "."),
+                (TokenKind::Ident, "foo"),
+                (TokenKind::Eof, "")
+            ],
+            tokens
+        );
+    }
+
+    #[test]
+    fn it_lexes_floats_as_a_single_token() {
+        let lexer = Lexer::new("3.14 2.5e-3 1E+9");
+        let tokens = lexer
+            .map(|t| t.map(|t| (t.kind, t.origin)))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            vec![
+                (TokenKind::Float, "3.14"),
+                (TokenKind::Float, "2.5e-3"),
+                (TokenKind::Float, "1E+9"),
+                (TokenKind::Eof, "")
+            ],
+            tokens
+        );
+    }
+
+    #[test]
+    fn it_reports_the_right_line_after_a_comment() {
+        let lexer = Lexer::new("# a comment $\ntask foo {} # another ?\n?");
+        let res: Result<Vec<Token>, LexError> = lexer.collect();
+        let Err(LexError::UnexpectedChar(e)) = res else {
+            panic!("should have failed");
+        };
+        assert_eq!(38, e.err_span.offset());
+        assert_eq!(3, e.line());
+    }
+
+    #[test]
+    fn it_fails_with_an_error_on_an_unterminated_block_comment() {
+        let lexer = Lexer::new("task /* foo {}");
+        let res: Result<Vec<Token>, LexError> = lexer.collect();
+        let Err(LexError::UnterminatedBlockComment(e)) = res else {
+            panic!("should have failed");
+        };
+        assert_eq!(5, e.err_span.offset());
+        assert_eq!(2, e.err_span.len());
+
+        let lexer = Lexer::new("/* /* */ foo");
+        let res: Result<Vec<Token>, LexError> = lexer.collect();
+        let Err(LexError::UnterminatedBlockComment(e)) = res else {
+            panic!("should have failed");
+        };
+        assert_eq!(0, e.err_span.offset());
+    }
+
+    #[test]
+    fn it_emits_a_single_eof_token_at_the_end() {
+        let lexer = Lexer::new("");
+        let tokens: Vec<Token> = lexer.collect::<Result<_, _>>().unwrap();
+        assert_eq!(
+            vec![Token {
+                origin: "",
+                offset: 0,
+                kind: TokenKind::Eof,
+                whole: "",
+                line: 1,
+                tab_width: 1,
+                merged: false,
+            }],
+            tokens
+        );
+
+        let mut lexer = Lexer::new("task foo {}  ");
+        let eof = lexer.by_ref().last().unwrap().unwrap();
+        assert_eq!(TokenKind::Eof, eof.kind);
+        assert_eq!(13, eof.offset);
+        assert!(lexer.next().is_none());
+    }
+
+    #[test]
+    fn it_returns_the_first_error_when_tokenizing() {
+        let e = crate::tokenize("task ? $").unwrap_err();
+        let LexError::UnexpectedChar(e) = e else {
+            panic!("should have been UnexpectedChar");
+        };
+        assert_eq!('?', e.token);
+        assert!(crate::tokenize_kinds("task $").is_err());
+    }
+
+    #[test]
+    fn it_knows_the_line_and_column_of_tokens() {
+        let tokens = crate::tokenize("task foo {\n  \"🦀\" bar\n}").unwrap();
+        let positions = tokens
+            .iter()
+            .map(|t| (t.kind, t.line(), t.column()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                (TokenKind::Task, 1, 1),
+                (TokenKind::Ident, 1, 6),
+                (TokenKind::LeftBrace, 1, 10),
+                (TokenKind::String, 2, 3),
+                (TokenKind::Ident, 2, 7),
+                (TokenKind::RightBrace, 3, 1),
+                (TokenKind::Eof, 3, 2),
+            ],
+            positions
+        );
+    }
+
+    #[test]
+    fn it_lexes_unicode_identifiers() {
+        for ident in ["café", "日本語", "foo_λ", "λ_foo3"] {
+            let input = format!("task {ident} {{}}");
+            let tokens = crate::tokenize(&input).unwrap();
+            assert_eq!(TokenKind::Ident, tokens[1].kind);
+            assert_eq!(ident, tokens[1].origin);
+            assert_eq!(5, tokens[1].offset);
+            assert_eq!(5 + ident.len() + 1, tokens[2].offset);
+        }
+    }
+
+    #[test]
+    fn it_escapes_control_chars_in_error_messages() {
+        for (input, escaped) in [("foo \0", "\\u{0}"), ("foo \x1b", "\\u{1b}")] {
+            let e = crate::tokenize(input).unwrap_err();
+            let LexError::UnexpectedChar(e) = e else {
+                panic!("should have been UnexpectedChar");
+            };
+            assert_eq!(format!("Unexpected token '{escaped}'"), e.to_string());
+            assert_eq!((4, 1), (e.err_span.offset(), e.err_span.len()));
+        }
+        // a form feed is whitespace, so it never makes it into an error
+        assert!(crate::tokenize("foo \x0c bar").is_ok());
+    }
+
+    #[test]
+    fn it_skips_no_break_spaces_as_whitespace() {
+        let tokens = crate::tokenize("task\u{a0}foo\u{a0}{}").unwrap();
+        let got = tokens
+            .iter()
+            .map(|t| (t.kind, t.offset))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                (TokenKind::Task, 0),
+                (TokenKind::Ident, 6),
+                (TokenKind::LeftBrace, 11),
+                (TokenKind::RightBrace, 12),
+                (TokenKind::Eof, 13),
+            ],
+            got
+        );
+    }
+
+    #[test]
+    fn it_rejects_zero_width_spaces() {
+        let e = crate::tokenize("task\u{200b}foo {}").unwrap_err();
+        let LexError::UnexpectedChar(e) = e else {
+            panic!("should have been UnexpectedChar");
+        };
+        assert_eq!('\u{200b}', e.token);
+        assert_eq!((4, 3), (e.err_span.offset(), e.err_span.len()));
+    }
+
+    #[test]
+    fn it_agrees_with_unicode_on_ascii_whitespace() {
+        for c in (0..=0x7f).map(char::from) {
+            assert_eq!(c.is_whitespace(), super::is_whitespace(c), "{c:?}");
+        }
+    }
+
+    #[test]
+    fn it_does_not_start_identifiers_with_non_xid_chars() {
+        let e = crate::tokenize("task ·foo {}").unwrap_err();
+        let LexError::UnexpectedChar(e) = e else {
+            panic!("should have been UnexpectedChar");
+        };
+        assert_eq!('·', e.token);
+    }
+
+    #[test]
+    fn it_slices_identifiers_on_char_boundaries() {
+        let mut lexer = Lexer::new("x λfooλ→");
+        let x = lexer.next().unwrap().unwrap();
+        assert_eq!(("x", 0), (x.origin, x.offset));
+        let ident = lexer.next().unwrap().unwrap();
+        assert_eq!(TokenKind::Ident, ident.kind);
+        assert_eq!(("λfooλ", 2), (ident.origin, ident.offset));
+
+        let e = lexer.next().unwrap().unwrap_err();
+        let LexError::UnexpectedChar(e) = e else {
+            panic!("should have been UnexpectedChar");
+        };
+        assert_eq!('→', e.token);
+        assert_eq!(9, e.err_span.offset());
+        assert_eq!('→'.len_utf8(), e.err_span.len());
+    }
+
+    #[test]
+    fn it_reports_a_multi_byte_char_after_an_identifier_at_its_byte_offset() {
+        let mut lexer = Lexer::new("foo→");
+        let foo = lexer.next().unwrap().unwrap();
+        assert_eq!(("foo", 0), (foo.origin, foo.offset));
+
+        let e = lexer.next().unwrap().unwrap_err();
+        let LexError::UnexpectedChar(e) = e else {
+            panic!("should have been UnexpectedChar");
+        };
+        assert_eq!('→', e.token);
+        assert_eq!(3, e.err_span.offset());
+        assert_eq!(3, e.err_span.len());
+        assert_eq!(TokenKind::Eof, lexer.next().unwrap().unwrap().kind);
+    }
+
+    #[test]
+    fn it_counts_crlf_as_a_single_line_break() {
+        let e = crate::tokenize("task foo {}\r\n$").unwrap_err();
+        let LexError::UnexpectedChar(e) = e else {
+            panic!("should have been UnexpectedChar");
+        };
+        assert_eq!(2, e.line());
+
+        let input = "task foo {\r\n}\n\r\ntask bar {}\n$";
+        let e = crate::tokenize(input).unwrap_err();
+        let LexError::UnexpectedChar(e) = e else {
+            panic!("should have been UnexpectedChar");
+        };
+        assert_eq!(5, e.line());
+
+        let tokens = crate::tokenize(input.trim_end_matches('$')).unwrap();
+        let lines = tokens.iter().map(|t| t.line()).collect::<Vec<_>>();
+        assert_eq!(vec![1, 1, 1, 2, 4, 4, 4, 4, 5], lines);
+        assert_eq!(1, tokens[3].column());
+    }
+
+    #[test]
+    fn it_shows_the_line_of_an_error() {
+        let LexError::UnexpectedChar(e) = crate::tokenize("task ? {\n}").unwrap_err() else {
+            panic!("should have been UnexpectedChar");
+        };
+        assert_eq!("task ? {", e.line_text());
+
+        let input = "task foo {\r\n  bar ?\r\n}\n";
+        let LexError::UnexpectedChar(e) = crate::tokenize(input).unwrap_err() else {
+            panic!("should have been UnexpectedChar");
+        };
+        assert_eq!((2, "  bar ?"), (e.line(), e.line_text()));
+
+        let LexError::UnexpectedChar(e) = crate::tokenize("a\nb\n?").unwrap_err() else {
+            panic!("should have been UnexpectedChar");
+        };
+        assert_eq!("?", e.line_text());
+    }
+
+    #[test]
+    fn it_does_not_count_a_lone_cr_as_a_line_break() {
+        let e = crate::tokenize("task foo {}\r$").unwrap_err();
+        let LexError::UnexpectedChar(e) = e else {
+            panic!("should have been UnexpectedChar");
+        };
+        assert_eq!(1, e.line());
+    }
+
+    #[test]
+    fn it_lexes_valid_utf8_bytes() {
+        let lexer = Lexer::from_bytes("task föö {}".as_bytes()).unwrap();
+        assert_eq!(
+            crate::tokenize("task föö {}").unwrap(),
+            lexer.collect::<Result<Vec<_>, _>>().unwrap()
+        );
+    }
+
+    #[test]
+    fn it_fails_with_an_error_on_a_truncated_utf8_sequence() {
+        let res = Lexer::from_bytes(b"task f\xc3");
+        let Err(LexError::InvalidUtf8(e)) = res else {
+            panic!("should have failed");
+        };
+        assert_eq!(6, e.offset);
+        assert_eq!(6, e.err_span.offset());
+        assert_eq!("Invalid UTF-8 at byte 6", e.to_string());
+        assert_eq!(&e.src[6..], "\u{fffd}");
+    }
+
+    #[test]
+    fn it_points_at_the_first_invalid_byte() {
+        let res = Lexer::from_bytes(b"task \xff foo \xfe");
+        let Err(LexError::InvalidUtf8(e)) = res else {
+            panic!("should have failed");
+        };
+        assert_eq!(5, e.offset);
+        assert_eq!(&e.src[5..5 + e.err_span.len()], "\u{fffd}");
+    }
+
+    #[test]
+    fn it_reuses_the_buffer_to_tokenize_into() {
+        let mut buf = Vec::new();
+        Lexer::new("task foo {}").tokenize_into(&mut buf).unwrap();
+        assert_eq!(crate::tokenize("task foo {}").unwrap(), buf);
+
+        let capacity = buf.capacity();
+        Lexer::new("bar").tokenize_into(&mut buf).unwrap();
+        let tokens = buf.iter().map(|t| (t.kind, t.origin)).collect::<Vec<_>>();
+        assert_eq!(
+            vec![(TokenKind::Ident, "bar"), (TokenKind::Eof, "")],
+            tokens
+        );
+        assert_eq!(capacity, buf.capacity());
+    }
+
+    #[test]
+    fn it_stops_tokenizing_into_the_buffer_at_the_first_error() {
+        let mut buf = Vec::new();
+        let res = Lexer::new("task ? foo").tokenize_into(&mut buf);
+        let Err(LexError::UnexpectedChar(e)) = res else {
+            panic!("should have failed");
+        };
+        assert_eq!('?', e.token);
+        assert_eq!(
+            vec![TokenKind::Task],
+            buf.iter().map(|t| t.kind).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn it_collects_all_errors() {
+        let (tokens, errors) = Lexer::new("task ?foo # bar $\n bar $").lex_all();
+        let kinds = tokens.iter().map(|t| t.kind).collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                TokenKind::Task,
+                TokenKind::Ident,
+                TokenKind::Ident,
+                TokenKind::Eof
+            ],
+            kinds
+        );
+        let errors = errors
+            .iter()
+            .map(|e| {
+                let LexError::UnexpectedChar(e) = e else {
+                    panic!("should have been UnexpectedChar");
+                };
+                (e.token, e.err_span.offset())
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(vec![('?', 5), ('$', 23)], errors);
+    }
+
+    #[test]
+    fn it_sorts_all_errors_by_offset() {
+        let input = "0x ?\n1__0 \"\\q\"\n'ab' $ /* /* */";
+        let (_, errors) = Lexer::new(input).lex_all();
+        let starts = errors.iter().map(|e| e.span().start).collect::<Vec<_>>();
+        assert_eq!(vec![0, 3, 6, 11, 15, 20, 22], starts);
+    }
+
+    #[test]
+    fn it_shares_the_source_between_errors() {
+        let input = "task foo { ? }\n".repeat(1000);
+        let (_, errors) = Lexer::new(&input).lex_all();
+        assert_eq!(1000, errors.len());
+
+        let sources = errors
+            .iter()
+            .map(|e| match e {
+                LexError::UnexpectedChar(e) => &e.src.text,
+                e => panic!("unexpected error {e:?}"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(input.as_str(), &**sources[0]);
+        assert!(sources.iter().all(|src| Arc::ptr_eq(src, sources[0])));
+    }
+
+    #[test]
+    fn it_displays_token_kinds() {
+        assert_eq!("{", format!("{}", TokenKind::LeftBrace));
+        assert_eq!("}", format!("{}", TokenKind::RightBrace));
+        assert_eq!("task", format!("{}", TokenKind::Task));
+        assert_eq!("identifier", format!("{}", TokenKind::Ident));
+        assert_eq!("end of input", format!("{}", TokenKind::Eof));
+    }
+
+    #[test]
+    fn it_displays_tokens() {
+        let tokens = crate::tokenize("task foo { \"bar\" 42 }").unwrap();
+        let displayed = tokens.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                "`task`",
+                "identifier `foo`",
+                "`{`",
+                "string `\"bar\"`",
+                "integer `42`",
+                "`}`",
+                "end of input"
+            ],
+            displayed
+        );
+    }
+
+    #[test]
+    fn it_converts_tokens_into_owned_tokens() {
+        let owned = {
+            let input = String::from("task foo {}");
+            crate::tokenize(&input)
+                .unwrap()
+                .iter()
+                .map(Token::to_owned_token)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            crate::OwnedToken {
+                text: "foo".to_string(),
+                offset: 5,
+                kind: TokenKind::Ident,
+            },
+            owned[1]
+        );
+        assert_eq!(5, owned.len());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn it_round_trips_owned_tokens_through_json() {
+        let owned = crate::tokenize("task foo {}")
+            .unwrap()
+            .iter()
+            .map(|t| t.to_owned_token())
+            .collect::<Vec<_>>();
+        let json = serde_json::to_string(&owned).unwrap();
+        assert!(
+            json.starts_with(r#"[{"text":"task","offset":0,"kind":"Task"}"#),
+            "{json}"
+        );
+        assert_eq!(
+            owned,
+            serde_json::from_str::<Vec<crate::OwnedToken>>(&json).unwrap()
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn it_converts_lex_errors_into_miette_errors() {
+        let e: miette::Error = crate::tokenize("task ?").unwrap_err().into();
+        assert_eq!("Unexpected token '?'", e.to_string());
+        assert!(e.downcast_ref::<LexError>().is_some());
+    }
+
+    #[test]
+    fn it_exposes_the_source_and_its_progress() {
+        let mut lexer = Lexer::new("task foo {}");
+        assert_eq!("task foo {}", lexer.source());
+        assert_eq!(0, lexer.byte_offset());
+
+        lexer.next();
+        assert_eq!(4, lexer.byte_offset());
+        lexer.next();
+        assert_eq!(8, lexer.byte_offset());
+        lexer.by_ref().for_each(drop);
+        assert_eq!(11, lexer.byte_offset());
+        assert_eq!("task foo {}", lexer.source());
+    }
+
+    #[test]
+    fn it_exposes_the_rest_of_the_input_after_an_error() {
+        let mut lexer = Lexer::new("task ?foo {}");
+        assert_eq!("task ?foo {}", lexer.rest());
+        lexer.next();
+        assert!(lexer.next().unwrap().is_err());
+        assert_eq!("foo {}", lexer.rest());
+        assert_eq!(&lexer.source()[lexer.byte_offset()..], lexer.rest());
+        lexer.by_ref().for_each(drop);
+        assert_eq!("", lexer.rest());
+    }
+
+    #[test]
+    fn it_tells_how_much_input_is_left() {
+        let mut lexer = Lexer::new("{}");
+        assert!(!lexer.is_at_end());
+        assert_eq!(2, lexer.remaining_bytes());
+        lexer.next();
+        assert!(!lexer.is_at_end());
+        assert_eq!(1, lexer.remaining_bytes());
+        lexer.by_ref().for_each(drop);
+        assert!(lexer.is_at_end());
+        assert_eq!(0, lexer.remaining_bytes());
+    }
+
+    #[test]
+    fn it_sorts_tokens_into_source_order() {
+        let tokens = crate::tokenize("task foo { bar(1, \"x\") }").unwrap();
+        let mut shuffled = tokens.clone();
+        shuffled.reverse();
+        shuffled.swap(1, 6);
+        shuffled.swap(0, 3);
+        assert_ne!(tokens, shuffled);
+        shuffled.sort();
+        assert_eq!(tokens, shuffled);
+    }
+
+    #[test]
+    fn it_orders_tokens_by_offset_before_their_text() {
+        let a = crate::tokenize("task").unwrap()[0];
+        let b = crate::tokenize("  ab").unwrap()[0];
+        assert!(a < b);
+        assert_eq!(core::cmp::Ordering::Equal, a.cmp(&a));
+    }
+
+    #[test]
+    fn it_hashes_token_kinds() {
+        let kinds = crate::tokenize_kinds("task a { b c }")
+            .unwrap()
+            .into_iter()
+            .collect::<std::collections::HashSet<_>>();
+        assert_eq!(5, kinds.len());
+    }
+
+    #[test]
+    fn it_counts_the_tokens() {
+        assert_eq!(Ok(4), crate::count_tokens("task foo {}").map_err(drop));
+        assert_eq!(
+            Ok(0),
+            crate::count_tokens("  # only a comment\n").map_err(drop)
+        );
+        let Err(LexError::UnexpectedChar(e)) = crate::count_tokens("task ? foo") else {
+            panic!("should have failed");
+        };
+        assert_eq!(5, e.err_span.offset());
+    }
+
+    #[test]
+    fn it_filters_the_tokens_by_kind() {
+        let idents = crate::tokens_of_kind("task a {} task b {}", TokenKind::Ident).unwrap();
+        assert_eq!(
+            vec![("a", 5), ("b", 15)],
+            idents
+                .iter()
+                .map(|t| (t.origin, t.offset))
+                .collect::<Vec<_>>()
+        );
+        assert!(crate::tokens_of_kind("task a", TokenKind::Float)
+            .unwrap()
+            .is_empty());
+        assert!(crate::tokens_of_kind("task a ?", TokenKind::Ident).is_err());
+    }
+
+    #[test]
+    fn it_merges_adjacent_tokens() {
+        let src = "x - > y - z";
+        let tokens = crate::tokenize(src).unwrap();
+        let merged = crate::merge_adjacent(&tokens, src, |a, b| {
+            (a.kind == TokenKind::Minus && b.kind == TokenKind::Greater).then_some(TokenKind::Arrow)
+        });
+        assert_eq!(
+            vec![
+                (TokenKind::Ident, "x", 0),
+                (TokenKind::Arrow, "- >", 2),
+                (TokenKind::Ident, "y", 6),
+                (TokenKind::Minus, "-", 8),
+                (TokenKind::Ident, "z", 10),
+                (TokenKind::Eof, "", 11),
+            ],
+            merged
+                .iter()
+                .map(|t| (t.kind, t.origin, t.offset))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn it_merges_a_merged_token_with_the_next() {
+        let src = "a.b.c d. e";
+        let tokens = crate::tokenize(src).unwrap();
+        let merged = crate::merge_adjacent(&tokens, src, |a, b| {
+            let touching = a.offset + a.origin.len() == b.offset;
+            match (a.kind, b.kind) {
+                (TokenKind::Ident | TokenKind::Path, TokenKind::Dot)
+                | (TokenKind::Path, TokenKind::Ident)
+                    if touching =>
+                {
+                    Some(TokenKind::Path)
+                }
+                _ => None,
+            }
+        });
+        assert_eq!(
+            vec![
+                (TokenKind::Path, "a.b.c"),
+                (TokenKind::Path, "d."),
+                (TokenKind::Ident, "e"),
+                (TokenKind::Eof, ""),
+            ],
+            merged
+                .iter()
+                .map(|t| (t.kind, t.origin))
+                .collect::<Vec<_>>()
+        );
+        assert!(crate::merge_adjacent(&[], src, |_, _| None).is_empty());
+    }
+
+    #[test]
+    fn it_keeps_the_whole_text_of_merged_literals() {
+        // none of these have the delimiters of a literal of the merged kind
+        for (src, kind) in [
+            ("r x", TokenKind::String),
+            ("é x", TokenKind::Variable),
+            ("é x", TokenKind::Char),
+        ] {
+            let tokens = crate::tokenize(src).unwrap();
+            let merged = crate::merge_adjacent(&tokens, src, |_, b| {
+                (b.kind != TokenKind::Eof).then_some(kind)
+            });
+            assert_eq!((kind, src), (merged[0].kind, merged[0].text()));
+            assert_eq!(src, merged[0].unescaped().unwrap());
+        }
+    }
+
+    #[test]
+    fn it_finds_the_span_of_the_first_error() {
+        assert_eq!(None, crate::first_error_span("task foo { bar(1) }"));
+        assert_eq!(None, crate::first_error_span(""));
+        assert_eq!(Some(5..6), crate::first_error_span("task ? foo \"bar"));
+        assert_eq!(Some(5..8), crate::first_error_span("task → {}"));
+        assert_eq!(Some(4..8), crate::first_error_span("foo \"bar"));
+    }
+
+    #[test]
+    fn it_slices_the_source_between_two_tokens() {
+        let src = "task foo {}";
+        let tokens = crate::tokenize(src).unwrap();
+        assert_eq!("{}", crate::source_between(src, &tokens[2], &tokens[3]));
+
+        let src = "task foo { bar(\"x\") }";
+        let tokens = crate::tokenize(src).unwrap();
+        assert_eq!(
+            "{ bar(\"x\") }",
+            crate::source_between(src, &tokens[2], &tokens[7])
+        );
+        assert_eq!("foo", crate::source_between(src, &tokens[1], &tokens[1]));
+        assert_eq!(
+            "task foo",
+            crate::source_between(src, &tokens[0], &tokens[1])
+        );
+    }
+
+    #[test]
+    fn it_names_every_kind() {
+        let names = [
+            (TokenKind::Task, "task"),
+            (TokenKind::If, "if"),
+            (TokenKind::Else, "else"),
+            (TokenKind::For, "for"),
+            (TokenKind::In, "in"),
+            (TokenKind::Return, "return"),
+            (TokenKind::True, "true"),
+            (TokenKind::False, "false"),
+            (TokenKind::Ident, "ident"),
+            (TokenKind::Path, "path"),
+            (TokenKind::String, "string"),
+            (TokenKind::Char, "char"),
+            (TokenKind::Integer, "integer"),
+            (TokenKind::Float, "float"),
+            (TokenKind::Duration, "duration"),
+            (TokenKind::ByteSize, "byte_size"),
+            (TokenKind::LeftBrace, "left_brace"),
+            (TokenKind::RightBrace, "right_brace"),
+            (TokenKind::LeftParen, "left_paren"),
+            (TokenKind::RightParen, "right_paren"),
+            (TokenKind::LeftBracket, "left_bracket"),
+            (TokenKind::RightBracket, "right_bracket"),
+            (TokenKind::Colon, "colon"),
+            (TokenKind::Comma, "comma"),
+            (TokenKind::Semicolon, "semicolon"),
+            (TokenKind::Dot, "dot"),
+            (TokenKind::Equals, "equals"),
+            (TokenKind::Minus, "minus"),
+            (TokenKind::Plus, "plus"),
+            (TokenKind::Star, "star"),
+            (TokenKind::Slash, "slash"),
+            (TokenKind::Percent, "percent"),
+            (TokenKind::Bang, "bang"),
+            (TokenKind::EqualEqual, "equal_equal"),
+            (TokenKind::NotEqual, "not_equal"),
+            (TokenKind::Greater, "greater"),
+            (TokenKind::GreaterEqual, "greater_equal"),
+            (TokenKind::Less, "less"),
+            (TokenKind::LessEqual, "less_equal"),
+            (TokenKind::AmpAmp, "amp_amp"),
+            (TokenKind::PipePipe, "pipe_pipe"),
+            (TokenKind::Arrow, "arrow"),
+            (TokenKind::DashDash, "dash_dash"),
+            (TokenKind::At, "at"),
+            (TokenKind::Variable, "variable"),
+            (TokenKind::Newline, "newline"),
+            (TokenKind::Whitespace, "whitespace"),
+            (TokenKind::Comment, "comment"),
+            (TokenKind::Unknown, "unknown"),
+            (TokenKind::Eof, "eof"),
+        ];
+        for (kind, name) in names {
+            assert_eq!(name, kind.name(), "{kind:?}");
+        }
+        let mut unique = names.map(|(_, name)| name);
+        unique.sort_unstable();
+        assert!(unique.windows(2).all(|pair| pair[0] != pair[1]));
+    }
+
+    #[test]
+    fn it_finds_the_token_at_an_offset() {
+        let tokens = crate::tokenize("task foo  {}").unwrap();
+        let kind_at = |offset| crate::token_at(&tokens, offset).map(|t| t.kind);
+        assert_eq!(Some(TokenKind::Task), kind_at(0));
+        assert_eq!(Some(TokenKind::Task), kind_at(3));
+        assert_eq!(None, kind_at(4));
+        assert_eq!(Some(TokenKind::Ident), kind_at(5));
+        assert_eq!(Some(TokenKind::Ident), kind_at(7));
+        assert_eq!(None, kind_at(8));
+        assert_eq!(None, kind_at(9));
+        assert_eq!(Some(TokenKind::RightBrace), kind_at(11));
+        assert_eq!(None, kind_at(12));
+        assert_eq!(None, kind_at(100));
+        assert_eq!(None, crate::token_at(&[], 0));
+    }
+
+    #[test]
+    #[should_panic = "comes before `start`"]
+    #[cfg(debug_assertions)]
+    fn it_asserts_the_order_of_the_tokens_to_slice_between() {
+        let src = "task foo {}";
+        let tokens = crate::tokenize(src).unwrap();
+        crate::source_between(src, &tokens[2], &tokens[1]);
+    }
+
+    #[test]
+    fn it_drops_the_trivia_again() {
+        let input = "task foo { # comment\n  /* block */ bar(\"x\") }\n";
+        let without = Lexer::new(input)
+            .with_trivia()
+            .without_trivia()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(crate::tokenize(input).unwrap(), without);
+    }
+
+    #[test]
+    fn it_keeps_the_errors_without_trivia() {
+        let results = Lexer::new("a ? b")
+            .with_trivia()
+            .without_trivia()
+            .collect::<Vec<_>>();
+        assert_eq!(4, results.len());
+        assert!(results[1].is_err());
+    }
+
+    #[test]
+    fn it_pairs_tokens_with_their_byte_range() {
+        let spanned = Lexer::new("task foo {}")
+            .spanned()
+            .map(|t| t.map(|(t, range)| (t.kind, range)))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            vec![
+                (TokenKind::Task, 0..4),
+                (TokenKind::Ident, 5..8),
+                (TokenKind::LeftBrace, 9..10),
+                (TokenKind::RightBrace, 10..11),
+                (TokenKind::Eof, 11..11),
+            ],
+            spanned
+        );
+    }
+
+    #[test]
+    fn it_peeks_at_the_next_spanned_token() {
+        let mut lexer = Lexer::new("task foo {}").peekable_spanned();
+        let Some(Ok((task, range))) = lexer.peek() else {
+            panic!("should have peeked at `task`");
+        };
+        assert_eq!((TokenKind::Task, 0..4), (task.kind, range.clone()));
+
+        let (task, range) = lexer.next().unwrap().unwrap();
+        assert_eq!((TokenKind::Task, 0..4), (task.kind, range));
+        let Some(Ok((foo, range))) = lexer.peek() else {
+            panic!("should have peeked at `foo`");
+        };
+        assert_eq!((TokenKind::Ident, 5..8), (foo.kind, range.clone()));
+    }
+
+    #[test]
+    fn it_lexes_raw_strings() {
+        let tokens = crate::tokenize(r###"r"a\nb" r#"has "quotes""# r##"a "# b"## r"###).unwrap();
+        let strings = tokens
+            .iter()
+            .map(|t| (t.kind, t.origin, t.unescaped().unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                (TokenKind::String, r#"r"a\nb""#, r"a\nb".into()),
+                (
+                    TokenKind::String,
+                    r##"r#"has "quotes""#"##,
+                    r#"has "quotes""#.into()
+                ),
+                (
+                    TokenKind::String,
+                    r###"r##"a "# b"##"###,
+                    r##"a "# b"##.into()
+                ),
+                (TokenKind::Ident, "r", "r".into()),
+                (TokenKind::Eof, "", "".into()),
+            ],
+            strings
+        );
+    }
+
+    #[test]
+    fn it_fails_with_an_error_on_an_unterminated_raw_string() {
+        let res = crate::tokenize(r##"task r#"foo" }"##);
+        let Err(LexError::UnterminatedString(e)) = res else {
+            panic!("should have failed");
+        };
+        assert_eq!(5, e.err_span.offset());
+        assert_eq!(3, e.err_span.len());
+    }
+
+    #[test]
+    fn it_lexes_heredocs() {
+        let input = "task foo {\n  script <<EOF\necho \"hi\"\n\nexit 0\n  EOF\n}";
+        let tokens = crate::tokenize(input).unwrap();
+        let kinds = tokens.iter().map(|t| t.kind).collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                TokenKind::Task,
+                TokenKind::Ident,
+                TokenKind::LeftBrace,
+                TokenKind::Ident,
+                TokenKind::String,
+                TokenKind::RightBrace,
+                TokenKind::Eof
+            ],
+            kinds
+        );
+        assert_eq!("<<EOF\necho \"hi\"\n\nexit 0\n  EOF", tokens[4].origin);
+        assert_eq!("echo \"hi\"\n\nexit 0\n", tokens[4].unescaped().unwrap());
+    }
+
+    #[test]
+    fn it_does_not_end_a_heredoc_on_the_tag_within_a_line() {
+        let input = "<<EOF\r\necho EOF here\r\nEOF.\r\nEOF\r\nfoo";
+        let tokens = crate::tokenize(input).unwrap();
+        assert_eq!(TokenKind::String, tokens[0].kind);
+        assert_eq!("<<EOF\r\necho EOF here\r\nEOF.\r\nEOF", tokens[0].origin);
+        assert_eq!("echo EOF here\r\nEOF.\r\n", tokens[0].unescaped().unwrap());
+        assert_eq!(TokenKind::Ident, tokens[1].kind);
+
+        let tokens = crate::tokenize("<<END\nEND").unwrap();
+        assert_eq!("", tokens[0].unescaped().unwrap());
+    }
+
+    #[test]
+    fn it_fails_with_an_error_on_an_unterminated_heredoc() {
+        let res = crate::tokenize("x <<EOF\necho EOF\n EOF foo");
+        let Err(LexError::UnterminatedString(e)) = res else {
+            panic!("should have failed");
+        };
+        assert_eq!(2, e.err_span.offset());
+        assert_eq!(23, e.err_span.len());
+    }
+
+    #[test]
+    fn it_lexes_the_same_tokens_after_a_reset() {
+        let mut lexer = Lexer::new("task foo { \"bar\" } ?");
+        let (first_tokens, first_errors) = lexer.lex_all();
+        assert!(lexer.next().is_none());
+
+        lexer.reset();
+        assert_eq!(0, lexer.byte_offset());
+        let (tokens, errors) = lexer.lex_all();
+        assert_eq!(first_tokens, tokens);
+        assert_eq!(first_errors.len(), errors.len());
+    }
+
+    #[test]
+    fn it_lexes_a_new_source_after_a_reset() {
+        let mut lexer = Lexer::new("task foo {}");
+        lexer.by_ref().for_each(drop);
+
+        lexer.reset_with("bar ?");
+        assert_eq!("bar ?", lexer.source());
+        let (tokens, errors) = lexer.lex_all();
+        assert_eq!(
+            ("bar", TokenKind::Ident),
+            (tokens[0].origin, tokens[0].kind)
+        );
+        let LexError::UnexpectedChar(e) = &errors[0] else {
+            panic!("should have been UnexpectedChar");
+        };
+        assert_eq!("bar ?", &*e.src);
+    }
+
+    #[test]
+    fn it_skips_a_leading_byte_order_mark() {
+        let tokens = crate::tokenize("\u{feff}task foo {}").unwrap();
+        assert_eq!(
+            vec![
+                (TokenKind::Task, 3),
+                (TokenKind::Ident, 8),
+                (TokenKind::LeftBrace, 12)
+            ],
+            tokens[..3]
+                .iter()
+                .map(|t| (t.kind, t.offset))
+                .collect::<Vec<_>>()
+        );
+
+        let mut lexer = Lexer::new("\u{feff}task");
+        assert_eq!(3, lexer.byte_offset());
+        lexer.by_ref().for_each(drop);
+        lexer.reset();
+        assert_eq!(3, lexer.byte_offset());
+
+        let Err(LexError::UnexpectedChar(e)) = crate::tokenize("task \u{feff}") else {
+            panic!("should have failed");
+        };
+        assert_eq!('\u{feff}', e.token);
+        assert!(Lexer::new_at("\u{feff}", 5).next().unwrap().is_err());
+    }
+
+    #[test]
+    fn it_lexes_a_region_at_its_offset_in_the_source() {
+        let tokens = Lexer::new_at("foo {}", 5)
+            .map(|t| t.map(|t| (t.kind, t.offset, t.column())))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            vec![
+                (TokenKind::Ident, 5, 1),
+                (TokenKind::LeftBrace, 9, 5),
+                (TokenKind::RightBrace, 10, 6),
+                (TokenKind::Eof, 11, 7),
+            ],
+            tokens
+        );
+    }
+
+    #[test]
+    fn it_finds_the_lines_of_tokens_and_errors_like_scanning_does() {
+        let src = "task a {\r\n  ? b\n\n\t?\r\n}\n? ä ?";
+        let line_of = |offset: usize| src[..offset].matches('\n').count() + 1;
+        let (tokens, errors) = Lexer::new(src).lex_all();
+        for token in tokens {
+            assert_eq!(line_of(token.offset), token.line(), "{token:?}");
+        }
+        let lines = errors
+            .iter()
+            .map(|e| {
+                let LexError::UnexpectedChar(e) = e else {
+                    panic!("should have been UnexpectedChar");
+                };
+                assert_eq!(line_of(e.err_span.offset()), e.line());
+                (e.line(), e.line_text())
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![(2, "  ? b"), (4, "\t?"), (6, "? ä ?"), (6, "? ä ?")],
+            lines
+        );
+    }
+
+    #[test]
+    fn it_counts_lines_back_to_an_earlier_token() {
+        let mut lexer = Lexer::new("a\nb\n\nc");
+        let tokens = lexer.by_ref().map(|t| t.unwrap()).collect::<Vec<_>>();
+        lexer.reset();
+        assert_eq!(1, lexer.next().unwrap().unwrap().line());
+        lexer.resume_from(&tokens[0]);
+        let lines = lexer.map(|t| t.unwrap().line()).collect::<Vec<_>>();
+        assert_eq!(vec![2, 4, 4], lines);
+    }
+
+    #[test]
+    fn it_only_indexes_the_lines_for_an_error() {
+        let mut lexer = Lexer::new("task a {\n}\n");
+        let mut buf = Vec::new();
+        lexer.tokenize_into(&mut buf).unwrap();
+        assert!(lexer.shared_lines.is_none());
+
+        lexer.reset_with("task a {\n?\n");
+        assert!(lexer.tokenize_into(&mut buf).is_err());
+        assert_eq!(Some(&[0, 9, 11][..]), lexer.shared_lines.as_deref());
+    }
+
+    #[test]
+    fn it_reports_errors_in_a_region_at_their_offset_in_the_source() {
+        let mut lexer = Lexer::new_at("foo\n?bar", 100);
+        lexer.next().unwrap().unwrap();
+        let Err(LexError::UnexpectedChar(e)) = lexer.next().unwrap() else {
+            panic!("should have failed");
+        };
+        assert_eq!(104, e.err_span.offset());
+        assert_eq!((2, "?bar"), (e.line(), e.line_text()));
+
+        #[cfg(feature = "std")]
+        {
+            let mut report = String::new();
+            miette::GraphicalReportHandler::new_themed(miette::GraphicalTheme::unicode_nocolor())
+                .render_report(&mut report, &e)
+                .unwrap();
+            assert!(report.contains("?bar"), "{report}");
+        }
+
+        lexer.reset();
+        assert_eq!(100, lexer.byte_offset());
+    }
+
+    #[test]
+    fn it_resumes_lexing_after_a_token_of_a_grown_source() {
+        let mut lexer = Lexer::new("task foo");
+        let tokens = lexer.by_ref().map(|t| t.unwrap()).collect::<Vec<_>>();
+
+        lexer.reset_with("task foo {}");
+        lexer.resume_from(&tokens[1]);
+        assert_eq!(8, lexer.byte_offset());
+        let resumed = lexer
+            .map(|t| t.map(|t| (t.kind, t.offset)))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            vec![
+                (TokenKind::LeftBrace, 9),
+                (TokenKind::RightBrace, 10),
+                (TokenKind::Eof, 11),
+            ],
+            resumed
+        );
+    }
+
+    #[test]
+    #[should_panic = "the source changed"]
+    #[cfg(debug_assertions)]
+    fn it_asserts_the_source_is_unchanged_up_to_the_resumed_token() {
+        let mut lexer = Lexer::new("task foo");
+        let tokens = lexer.by_ref().map(|t| t.unwrap()).collect::<Vec<_>>();
+        lexer.reset_with("task bar {}");
+        lexer.resume_from(&tokens[1]);
+    }
+
+    #[test]
+    fn it_reports_a_run_of_unknown_punctuation_as_one_error() {
+        let (tokens, errors) = Lexer::new("task foo ?? bar").lex_all();
+        let kinds = tokens.iter().map(|t| t.kind).collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                TokenKind::Task,
+                TokenKind::Ident,
+                TokenKind::Ident,
+                TokenKind::Eof
+            ],
+            kinds
+        );
+        let [LexError::UnexpectedSequence(e)] = &errors[..] else {
+            panic!("should have been a single UnexpectedSequence");
+        };
+        assert_eq!("??", e.sequence);
+        assert_eq!(9, e.err_span.offset());
+        assert_eq!(2, e.err_span.len());
+    }
+
+    #[test]
+    fn it_stops_a_run_of_unknown_punctuation_at_known_tokens() {
+        let (tokens, errors) = Lexer::new("a ?~{ b ?/* c */ ?").lex_all();
+        let kinds = tokens.iter().map(|t| t.kind).collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                TokenKind::Ident,
+                TokenKind::LeftBrace,
+                TokenKind::Ident,
+                TokenKind::Eof
+            ],
+            kinds
+        );
+        let spans = errors
+            .iter()
+            .map(|e| match e {
+                LexError::UnexpectedSequence(e) => (e.err_span.offset(), e.err_span.len()),
+                LexError::UnexpectedChar(e) => (e.err_span.offset(), e.err_span.len()),
+                e => panic!("unexpected error {e:?}"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(vec![(2, 2), (8, 1), (17, 1)], spans);
+    }
+
+    #[test]
+    fn it_stops_a_run_of_unknown_punctuation_at_operators() {
+        let (tokens, errors) = Lexer::new("a ?>= b ?|| c ?! d ?&& e ?<f ?&|").lex_all();
+        let kinds = tokens.iter().map(|t| t.kind).collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                TokenKind::Ident,
+                TokenKind::GreaterEqual,
+                TokenKind::Ident,
+                TokenKind::PipePipe,
+                TokenKind::Ident,
+                TokenKind::Bang,
+                TokenKind::Ident,
+                TokenKind::AmpAmp,
+                TokenKind::Ident,
+                TokenKind::Less,
+                TokenKind::Ident,
+                TokenKind::Eof
+            ],
+            kinds
+        );
+        let errors = errors
+            .iter()
+            .map(|e| match e {
+                LexError::UnexpectedSequence(e) => e.sequence.clone(),
+                LexError::UnexpectedChar(e) => e.token.to_string(),
+                e => panic!("unexpected error {e:?}"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["?", "?", "?", "?", "?", "?&|"], errors);
+    }
+
+    #[test]
+    fn it_classifies_token_kinds() {
+        let classes = [
+            (TokenKind::Task, true, false),
+            (TokenKind::If, true, false),
+            (TokenKind::Else, true, false),
+            (TokenKind::For, true, false),
+            (TokenKind::In, true, false),
+            (TokenKind::Return, true, false),
+            (TokenKind::True, true, true),
+            (TokenKind::False, true, true),
+            (TokenKind::Ident, false, false),
+            (TokenKind::Path, false, false),
+            (TokenKind::String, false, true),
+            (TokenKind::Char, false, true),
+            (TokenKind::Integer, false, true),
+            (TokenKind::Float, false, true),
+            (TokenKind::Duration, false, true),
+            (TokenKind::ByteSize, false, true),
+            (TokenKind::LeftBrace, false, false),
+            (TokenKind::RightBrace, false, false),
+            (TokenKind::LeftParen, false, false),
+            (TokenKind::RightParen, false, false),
+            (TokenKind::LeftBracket, false, false),
+            (TokenKind::RightBracket, false, false),
+            (TokenKind::Colon, false, false),
+            (TokenKind::Comma, false, false),
+            (TokenKind::Semicolon, false, false),
+            (TokenKind::Dot, false, false),
+            (TokenKind::Equals, false, false),
+            (TokenKind::Minus, false, false),
+            (TokenKind::Plus, false, false),
+            (TokenKind::Star, false, false),
+            (TokenKind::Slash, false, false),
+            (TokenKind::Percent, false, false),
+            (TokenKind::Bang, false, false),
+            (TokenKind::EqualEqual, false, false),
+            (TokenKind::NotEqual, false, false),
+            (TokenKind::Greater, false, false),
+            (TokenKind::GreaterEqual, false, false),
+            (TokenKind::Less, false, false),
+            (TokenKind::LessEqual, false, false),
+            (TokenKind::AmpAmp, false, false),
+            (TokenKind::PipePipe, false, false),
+            (TokenKind::Arrow, false, false),
+            (TokenKind::DashDash, false, false),
+            (TokenKind::At, false, false),
+            (TokenKind::Variable, false, false),
+            (TokenKind::Newline, false, false),
+            (TokenKind::Unknown, false, false),
+            (TokenKind::Eof, false, false),
+        ];
+        for (kind, keyword, literal) in classes {
+            assert_eq!(keyword, kind.is_keyword(), "{kind:?}");
+            assert_eq!(literal, kind.is_literal(), "{kind:?}");
+            assert!(!kind.is_trivia(), "{kind:?}");
+        }
+        for kind in [TokenKind::Whitespace, TokenKind::Comment] {
+            assert!(!kind.is_keyword(), "{kind:?}");
+            assert!(!kind.is_literal(), "{kind:?}");
+            assert!(kind.is_trivia(), "{kind:?}");
+        }
+
+        let tokens = crate::tokenize("task foo").unwrap();
+        assert!(tokens[0].is_keyword());
+        assert!(!tokens[1].is_keyword());
+    }
+
+    #[test]
+    fn it_emits_trivia_tokens_when_asked_to() {
+        let input = "task foo {}  \n\t # hi\n/* a\n /* b */ */";
+        let tokens = Lexer::new(input)
+            .with_trivia()
+            .map(|t| t.map(|t| (t.kind, t.origin)))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            vec![
+                (TokenKind::Task, "task"),
+                (TokenKind::Whitespace, " "),
+                (TokenKind::Ident, "foo"),
+                (TokenKind::Whitespace, " "),
+                (TokenKind::LeftBrace, "{"),
+                (TokenKind::RightBrace, "}"),
+                (TokenKind::Whitespace, "  \n\t "),
+                (TokenKind::Comment, "# hi"),
+                (TokenKind::Whitespace, "\n"),
+                (TokenKind::Comment, "/* a\n /* b */ */"),
+                (TokenKind::Eof, ""),
+            ],
+            tokens
+        );
+
+        let without_trivia = tokens
+            .into_iter()
+            .filter(|(kind, _)| !kind.is_trivia())
+            .collect::<Vec<_>>();
+        let default = crate::tokenize(input)
+            .unwrap()
+            .into_iter()
+            .map(|t| (t.kind, t.origin))
+            .collect::<Vec<_>>();
+        assert_eq!(default, without_trivia);
+    }
+
+    #[test]
+    fn it_lexes_char_literals() {
+        let tokens = crate::tokenize(r"'a' '\n' '\'' 'λ' '\\'").unwrap();
+        let chars = tokens
+            .iter()
+            .map(|t| (t.kind, t.origin, t.unescaped().unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                (TokenKind::Char, "'a'", "a".into()),
+                (TokenKind::Char, r"'\n'", "\n".into()),
+                (TokenKind::Char, r"'\''", "'".into()),
+                (TokenKind::Char, "'λ'", "λ".into()),
+                (TokenKind::Char, r"'\\'", "\\".into()),
+                (TokenKind::Eof, "", "".into()),
+            ],
+            chars
+        );
+    }
+
+    #[test]
+    fn it_fails_with_an_error_on_invalid_char_literals() {
+        for (input, offset, len) in [("x ''", 2, 2), ("x 'ab' y", 2, 4), ("x 'a\ny", 2, 2)] {
+            let res = crate::tokenize(input);
+            let Err(LexError::InvalidChar(e)) = res else {
+                panic!("{input:?} should have failed");
+            };
+            assert_eq!(offset, e.err_span.offset(), "{input:?}");
+            assert_eq!(len, e.err_span.len(), "{input:?}");
+        }
+
+        let Err(LexError::InvalidEscape(e)) = crate::tokenize(r"'\q'") else {
+            panic!("should have failed");
+        };
+        assert_eq!(1, e.err_span.offset());
+        assert_eq!(2, e.err_span.len());
+    }
+
+    #[test]
+    fn it_lexes_prefixed_integers() {
+        let tokens = crate::tokenize("0xFF 0o755 0b1010 0xdead_beef 42 1.5").unwrap();
+        let integers = tokens
+            .iter()
+            .map(|t| (t.kind, t.origin, t.radix()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                (TokenKind::Integer, "0xFF", Some(16)),
+                (TokenKind::Integer, "0o755", Some(8)),
+                (TokenKind::Integer, "0b1010", Some(2)),
+                (TokenKind::Integer, "0xdead_beef", Some(16)),
+                (TokenKind::Integer, "42", Some(10)),
+                (TokenKind::Float, "1.5", None),
+                (TokenKind::Eof, "", None),
+            ],
+            integers
+        );
+    }
+
+    #[test]
+    fn it_stops_prefixed_integers_at_the_first_char_outside_their_radix() {
+        let tokens = crate::tokenize("0b102 0o78").unwrap();
+        let origins = tokens.iter().map(|t| t.origin).collect::<Vec<_>>();
+        assert_eq!(vec!["0b10", "2", "0o7", "8", ""], origins);
+    }
+
+    #[test]
+    fn it_fails_with_an_error_on_a_prefix_without_digits() {
+        for input in ["port 0x", "port 0x_ ", "port 0bz"] {
+            let Err(LexError::MissingDigits(e)) = crate::tokenize(input) else {
+                panic!("{input:?} should have failed");
+            };
+            assert_eq!(5, e.err_span.offset());
+            assert_eq!(2, e.err_span.len());
+        }
     }
 }
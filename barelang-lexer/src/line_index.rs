@@ -0,0 +1,85 @@
+use alloc::sync::Arc;
+
+/// The starts of the lines of a source, to find the line and column of many
+/// offsets without scanning the source from its beginning for each of them.
+///
+/// Lines are separated by `\n`, so a Windows `\r\n` counts as a single line
+/// break, while a lone `\r` does not start a new line. Columns are counted in
+/// chars, like [`crate::Token::column`] with a tab width of 1.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineIndex<'de> {
+    src: &'de str,
+    // the byte offset of each line, the first one being 0, shared with the
+    // errors of a lexer
+    pub(crate) line_starts: Arc<[usize]>,
+}
+
+impl<'de> LineIndex<'de> {
+    pub fn new(src: &'de str) -> Self {
+        let line_starts = core::iter::once(0)
+            .chain(src.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self { src, line_starts }
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    /// The 1-based line of the byte `offset`.
+    pub fn line(&self, offset: usize) -> usize {
+        line_of(&self.line_starts, offset)
+    }
+
+    /// The 1-based line and column of the byte `offset`, which must be on a
+    /// char boundary.
+    ///
+    /// Finding the line is a binary search, only the column is counted in
+    /// chars from the start of its line.
+    pub fn line_col(&self, offset: usize) -> (usize, usize) {
+        let line = self.line(offset);
+        let line_start = self.line_starts[line - 1];
+        (line, self.src[line_start..offset].chars().count() + 1)
+    }
+}
+
+// the 1-based line of the byte `offset` by the starts of the lines
+pub(crate) fn line_of(line_starts: &[usize], offset: usize) -> usize {
+    line_starts.partition_point(|&start| start <= offset)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::tokenize;
+
+    use super::LineIndex;
+
+    #[test]
+    fn it_agrees_with_scanning_the_source() {
+        let src = "task föö {\r\n  bär(\"λ\")\n\n}\r🦀 x\n";
+        let index = LineIndex::new(src);
+        for (offset, _) in src.char_indices().chain([(src.len(), ' ')]) {
+            let line = src[..offset].matches('\n').count() + 1;
+            let line_start = src[..offset].rfind('\n').map_or(0, |i| i + 1);
+            let column = src[line_start..offset].chars().count() + 1;
+            assert_eq!((line, column), index.line_col(offset), "at offset {offset}");
+        }
+    }
+
+    #[test]
+    fn it_agrees_with_the_token_positions() {
+        let src = "task a {\n  b ä\n}\n\ntask ü {}";
+        let index = LineIndex::new(src);
+        for token in tokenize(src).unwrap() {
+            assert_eq!((token.line(), token.column()), index.line_col(token.offset));
+        }
+    }
+
+    #[test]
+    fn it_counts_the_lines() {
+        assert_eq!(1, LineIndex::new("").line_count());
+        assert_eq!(2, LineIndex::new("a\nb").line_count());
+        assert_eq!(3, LineIndex::new("a\r\nb\n").line_count());
+        assert_eq!((3, 1), LineIndex::new("a\r\nb\n").line_col(5));
+    }
+}
@@ -0,0 +1,181 @@
+use alloc::collections::VecDeque;
+
+use crate::{
+    error::{LexError, UnexpectedTokenError},
+    span::SourceSpan,
+    Lexer, Token, TokenKind,
+};
+
+pub struct PeekableLexer<'de> {
+    lexer: Lexer<'de>,
+    peeked: VecDeque<Result<Token<'de>, LexError>>,
+}
+
+impl<'de> PeekableLexer<'de> {
+    pub fn new(input: &'de str) -> Self {
+        Self::from(Lexer::new(input))
+    }
+
+    pub fn peek(&mut self) -> Option<&Result<Token<'de>, LexError>> {
+        self.fill(1);
+        self.peeked.front()
+    }
+
+    pub fn peek2(&mut self) -> Option<&Result<Token<'de>, LexError>> {
+        self.fill(2);
+        self.peeked.get(1)
+    }
+
+    /// Consumes the next token if it is of `kind`.
+    ///
+    /// A token of another kind is left to recover from, and the error points
+    /// at it, or at the end of the input once the [`TokenKind::Eof`] is
+    /// consumed.
+    pub fn expect(&mut self, kind: TokenKind) -> Result<Token<'de>, LexError> {
+        let (src, found, err_span) = match self.peek() {
+            Some(Ok(token)) if token.kind == kind => {
+                return self.next().expect("the token was just peeked");
+            }
+            Some(Ok(token)) => (token.error_source(), token.kind, token.span().into()),
+            Some(Err(_)) => return self.next().expect("the error was just peeked"),
+            None => {
+                let end = self.lexer.byte_offset();
+                let src = self.lexer.shared_source();
+                (src, TokenKind::Eof, SourceSpan::from(end..end))
+            }
+        };
+        Err(UnexpectedTokenError {
+            src,
+            expected: kind,
+            found,
+            err_span,
+        }
+        .into())
+    }
+
+    fn fill(&mut self, n: usize) {
+        while self.peeked.len() < n {
+            let Some(next) = self.lexer.next() else {
+                break;
+            };
+            self.peeked.push_back(next);
+        }
+    }
+}
+
+impl<'de> From<Lexer<'de>> for PeekableLexer<'de> {
+    fn from(lexer: Lexer<'de>) -> Self {
+        Self {
+            lexer,
+            peeked: VecDeque::with_capacity(2),
+        }
+    }
+}
+
+impl<'de> Iterator for PeekableLexer<'de> {
+    type Item = Result<Token<'de>, LexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.peeked.pop_front().or_else(|| self.lexer.next())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{error::LexError, Token, TokenKind};
+
+    use super::PeekableLexer;
+
+    fn peeked_kind(peeked: Option<&Result<Token, LexError>>) -> TokenKind {
+        peeked.unwrap().as_ref().unwrap().kind
+    }
+
+    #[test]
+    fn it_peeks_without_consuming() {
+        let mut lexer = PeekableLexer::new("task foo {}");
+        assert_eq!(TokenKind::Task, peeked_kind(lexer.peek()));
+        assert_eq!(TokenKind::Task, peeked_kind(lexer.peek()));
+        assert_eq!(TokenKind::Ident, peeked_kind(lexer.peek2()));
+        assert_eq!(0, lexer.peek().unwrap().as_ref().unwrap().offset);
+
+        let task = lexer.next().unwrap().unwrap();
+        assert_eq!(TokenKind::Task, task.kind);
+        assert_eq!(0, task.offset);
+
+        assert_eq!(TokenKind::Ident, peeked_kind(lexer.peek()));
+        assert_eq!(TokenKind::LeftBrace, peeked_kind(lexer.peek2()));
+        let foo = lexer.next().unwrap().unwrap();
+        assert_eq!(TokenKind::Ident, foo.kind);
+        assert_eq!(5, foo.offset);
+
+        let kinds = lexer.map(|t| t.unwrap().kind).collect::<Vec<_>>();
+        assert_eq!(
+            vec![TokenKind::LeftBrace, TokenKind::RightBrace, TokenKind::Eof],
+            kinds
+        );
+    }
+
+    #[test]
+    fn it_runs_out_of_tokens_to_peek() {
+        let mut lexer = PeekableLexer::new("");
+        assert_eq!(TokenKind::Eof, peeked_kind(lexer.peek()));
+        assert!(lexer.peek2().is_none());
+        assert!(lexer.next().is_some());
+        assert!(lexer.peek().is_none());
+        assert!(lexer.next().is_none());
+    }
+
+    #[test]
+    fn it_preserves_peeked_errors() {
+        let mut lexer = PeekableLexer::new("task ?");
+        let Some(Err(LexError::UnexpectedChar(e))) = lexer.peek2() else {
+            panic!("should have failed");
+        };
+        assert_eq!('?', e.token);
+
+        assert_eq!(TokenKind::Task, lexer.next().unwrap().unwrap().kind);
+        let Some(Err(LexError::UnexpectedChar(e))) = lexer.next() else {
+            panic!("should have failed");
+        };
+        assert_eq!('?', e.token);
+        assert_eq!(5, e.err_span.offset());
+    }
+
+    #[test]
+    fn it_consumes_an_expected_token() {
+        let mut lexer = PeekableLexer::new("task foo");
+        let task = lexer.expect(TokenKind::Task).unwrap();
+        assert_eq!(("task", 0), (task.origin, task.offset));
+        assert_eq!(TokenKind::Ident, peeked_kind(lexer.peek()));
+    }
+
+    #[test]
+    fn it_reports_an_unexpected_token_without_consuming_it() {
+        let mut lexer = PeekableLexer::new("task foo");
+        lexer.next();
+        let Err(LexError::UnexpectedToken(e)) = lexer.expect(TokenKind::LeftBrace) else {
+            panic!("should have failed");
+        };
+        assert_eq!("Expected {, found identifier", e.to_string());
+        assert_eq!((5, 3), (e.err_span.offset(), e.err_span.len()));
+        assert_eq!(TokenKind::Ident, lexer.next().unwrap().unwrap().kind);
+    }
+
+    #[test]
+    fn it_reports_an_unexpected_end_of_input() {
+        let mut lexer = PeekableLexer::new("task");
+        lexer.next();
+        let Err(LexError::UnexpectedToken(e)) = lexer.expect(TokenKind::Ident) else {
+            panic!("should have failed");
+        };
+        assert_eq!("Expected identifier, found end of input", e.to_string());
+        assert_eq!((4, 0), (e.err_span.offset(), e.err_span.len()));
+
+        lexer.next();
+        let Err(LexError::UnexpectedToken(e)) = lexer.expect(TokenKind::Ident) else {
+            panic!("should have failed");
+        };
+        assert_eq!(TokenKind::Eof, e.found);
+        assert_eq!((4, 0), (e.err_span.offset(), e.err_span.len()));
+    }
+}
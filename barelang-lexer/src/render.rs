@@ -0,0 +1,142 @@
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt::Write;
+
+use crate::{Lexer, LineIndex, Span};
+
+/// Renders the tokens and errors of all of `input`, one per line, as text that
+/// is stable enough to compare in snapshot tests.
+///
+/// A line reads like `5..8 ident "foo"`: the byte range, the
+/// [`crate::TokenKind::name`] and the text. An error in between reads like
+/// `9..10 error "Unexpected token '?'"`, with its message as the text.
+pub fn render_tokens(input: &str) -> String {
+    let mut rendered = String::new();
+    for (span, kind, text) in dump(input) {
+        writeln!(rendered, "{}..{} {kind} {text:?}", span.start, span.end())
+            .expect("writing to a String cannot fail");
+    }
+    rendered
+}
+
+/// Renders the tokens and errors of all of `input` as a table for debugging,
+/// with a column each for the byte offset, the 1-based line and column, the
+/// [`crate::TokenKind::name`] and the text.
+///
+/// Errors are rows of the `error` kind, with their message as the text.
+pub fn debug_table(input: &str) -> String {
+    let lines = LineIndex::new(input);
+    let rows = dump(input)
+        .into_iter()
+        .map(|(span, kind, text)| {
+            let (line, column) = lines.line_col(span.start);
+            [
+                span.start.to_string(),
+                format!("{line}:{column}"),
+                kind.to_string(),
+                format!("{text:?}"),
+            ]
+        })
+        .collect::<Vec<_>>();
+    let header = ["offset", "line:col", "kind", "text"].map(String::from);
+
+    let mut widths = [0; 3];
+    for row in core::iter::once(&header).chain(&rows) {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let mut table = String::new();
+    for [offset, line_col, kind, text] in core::iter::once(&header).chain(&rows) {
+        let [w0, w1, w2] = widths;
+        writeln!(table, "{offset:w0$} | {line_col:w1$} | {kind:w2$} | {text}")
+            .expect("writing to a String cannot fail");
+    }
+    table
+}
+
+// the span, kind name and text of every token and error in source order, with
+// `error` as the kind and the message as the text of errors
+pub(crate) fn dump(input: &str) -> Vec<(Span, &'static str, String)> {
+    let (tokens, errors) = Lexer::new(input).lex_all();
+    let mut entries = tokens
+        .iter()
+        .map(|token| (token.span(), token.kind.name(), token.origin.to_string()))
+        .chain(errors.iter().map(|e| (e.span(), "error", e.to_string())))
+        .collect::<Vec<_>>();
+    // the Eof comes last, even after an error at the very end of the input
+    entries.sort_by_key(|(span, kind, _)| (span.start, *kind == "eof"));
+    entries
+}
+
+#[cfg(test)]
+mod test {
+    use insta::assert_snapshot;
+
+    use super::{debug_table, render_tokens};
+
+    #[test]
+    fn it_renders_a_task() {
+        assert_snapshot!(render_tokens("task foo { deploy(\"x\\n\", 0x2a) }\n"), @r#"
+        0..4 task "task"
+        5..8 ident "foo"
+        9..10 left_brace "{"
+        11..17 ident "deploy"
+        17..18 left_paren "("
+        18..23 string "\"x\\n\""
+        23..24 comma ","
+        25..29 integer "0x2a"
+        29..30 right_paren ")"
+        31..32 right_brace "}"
+        33..33 eof ""
+        "#);
+    }
+
+    #[test]
+    fn it_renders_errors_between_the_tokens() {
+        assert_snapshot!(render_tokens("foo ? bar \"baz"), @r#"
+        0..3 ident "foo"
+        4..5 error "Unexpected token '?'"
+        6..9 ident "bar"
+        10..14 error "Unterminated string"
+        14..14 eof ""
+        "#);
+    }
+
+    #[test]
+    fn it_renders_heredocs_and_operators() {
+        assert_snapshot!(render_tokens("<<EOF\nbody\nEOF\na -> -- $HOME"), @r#"
+        0..14 string "<<EOF\nbody\nEOF"
+        15..16 ident "a"
+        17..19 arrow "->"
+        20..22 dash_dash "--"
+        23..28 variable "$HOME"
+        28..28 eof ""
+        "#);
+    }
+
+    #[test]
+    fn it_renders_a_debug_table() {
+        assert_snapshot!(debug_table("task foo {}"), @r#"
+        offset | line:col | kind        | text
+        0      | 1:1      | task        | "task"
+        5      | 1:6      | ident       | "foo"
+        9      | 1:10     | left_brace  | "{"
+        10     | 1:11     | right_brace | "}"
+        11     | 1:12     | eof         | ""
+        "#);
+    }
+
+    #[test]
+    fn it_puts_errors_and_later_lines_into_the_debug_table() {
+        assert_snapshot!(debug_table("foo\n  ?"), @r#"
+        offset | line:col | kind  | text
+        0      | 1:1      | ident | "foo"
+        6      | 2:3      | error | "Unexpected token '?'"
+        7      | 2:4      | eof   | ""
+        "#);
+    }
+}
@@ -0,0 +1,152 @@
+#[cfg(feature = "std")]
+pub(crate) use miette::SourceSpan;
+
+use crate::Token;
+
+/// Stands in for miette's `SourceSpan` without `std`, with the parts of it the
+/// lexer uses.
+#[cfg(not(feature = "std"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct SourceSpan {
+    offset: usize,
+    len: usize,
+}
+
+#[cfg(not(feature = "std"))]
+impl SourceSpan {
+    pub(crate) fn new(offset: usize, len: usize) -> Self {
+        Self { offset, len }
+    }
+
+    pub(crate) fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl From<core::ops::Range<usize>> for SourceSpan {
+    fn from(range: core::ops::Range<usize>) -> Self {
+        Self::new(range.start, range.end - range.start)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub len: usize,
+}
+
+impl Span {
+    pub fn end(&self) -> usize {
+        self.start + self.len
+    }
+
+    /// The position of the start of the span in `src` the way the Language
+    /// Server Protocol counts it: the 0-based line and the 0-based character
+    /// in UTF-16 code units, where a char outside the BMP like `🦀` counts
+    /// twice.
+    pub fn to_utf16(&self, src: &str) -> (u32, u32) {
+        utf16_position(src, self.start)
+    }
+
+    /// The smallest span covering both `self` and `other`.
+    pub fn join(self, other: Span) -> Span {
+        let start = self.start.min(other.start);
+        Span {
+            start,
+            len: self.end().max(other.end()) - start,
+        }
+    }
+}
+
+// the LSP position of the byte `offset` of `src`
+pub(crate) fn utf16_position(src: &str, offset: usize) -> (u32, u32) {
+    let before = &src[..offset];
+    let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+    let line = before.matches('\n').count();
+    let character = before[line_start..].encode_utf16().count();
+    (to_u32(line), to_u32(character))
+}
+
+fn to_u32(n: usize) -> u32 {
+    u32::try_from(n).expect("LSP positions only go up to u32::MAX")
+}
+
+impl From<Span> for SourceSpan {
+    fn from(span: Span) -> Self {
+        SourceSpan::from(span.start..span.end())
+    }
+}
+
+impl From<&Token<'_>> for SourceSpan {
+    fn from(token: &Token<'_>) -> Self {
+        token.span().into()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{tokenize, TokenKind};
+
+    use super::{SourceSpan, Span};
+
+    #[test]
+    fn it_spans_every_token() {
+        let spans = tokenize("task foo {}")
+            .unwrap()
+            .iter()
+            .map(|t| (t.kind, t.span()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                (TokenKind::Task, Span { start: 0, len: 4 }),
+                (TokenKind::Ident, Span { start: 5, len: 3 }),
+                (TokenKind::LeftBrace, Span { start: 9, len: 1 }),
+                (TokenKind::RightBrace, Span { start: 10, len: 1 }),
+                (TokenKind::Eof, Span { start: 11, len: 0 }),
+            ],
+            spans
+        );
+    }
+
+    #[test]
+    fn it_joins_spans_of_several_tokens() {
+        let tokens = tokenize("task foo {}").unwrap();
+        let body = tokens[2].span().join(tokens[3].span());
+        assert_eq!(Span { start: 9, len: 2 }, body);
+        assert_eq!(11, body.end());
+        assert_eq!(body, tokens[3].span().join(tokens[2].span()));
+    }
+
+    #[test]
+    fn it_counts_positions_in_utf16_code_units() {
+        let src = "task \"🦀\" foo\n  \"é\" bar";
+        let tokens = tokenize(src).unwrap();
+        let (foo, bar) = (&tokens[2], &tokens[4]);
+        // the crab is four bytes, one char and two UTF-16 code units
+        assert_eq!((12, 1, 10), (foo.offset, foo.line(), foo.column()));
+        assert_eq!((0, 10), foo.span().to_utf16(src));
+        assert_eq!((0, 10)..(0, 13), foo.utf16_range(src));
+        assert_eq!((1, 6)..(1, 9), bar.utf16_range(src));
+        assert_eq!((0, 5)..(0, 9), tokens[1].utf16_range(src));
+    }
+
+    #[test]
+    fn it_converts_into_a_source_span() {
+        let span = SourceSpan::from(Span { start: 5, len: 3 });
+        assert_eq!(5, span.offset());
+        assert_eq!(3, span.len());
+    }
+
+    #[test]
+    fn it_converts_a_token_into_a_source_span() {
+        let tokens = tokenize("task foo {}").unwrap();
+        let span = SourceSpan::from(&tokens[3]);
+        assert_eq!(TokenKind::RightBrace, tokens[3].kind);
+        assert_eq!((10, 1), (span.offset(), span.len()));
+    }
+}
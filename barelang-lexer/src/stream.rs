@@ -0,0 +1,111 @@
+use alloc::{string::String, vec::Vec};
+
+use crate::{error::LexError, Lexer, Token, TokenKind};
+
+/// Tokens stored together with the source they were lexed from, so they can be
+/// kept around for as long as needed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenStream {
+    source: String,
+    // the offset, length, kind and line of each token
+    tokens: Vec<(usize, usize, TokenKind, usize)>,
+}
+
+impl TokenStream {
+    /// Lexes all of `source`, stopping at the first error.
+    pub fn lex(source: impl Into<String>) -> Result<Self, LexError> {
+        let source = source.into();
+        let tokens = Lexer::new(&source)
+            .map(|t| t.map(|t| (t.offset, t.origin.len(), t.kind, t.line)))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { source, tokens })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn len(&self) -> usize {
+        self.tokens.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<Token<'_>> {
+        self.tokens.get(index).map(|&token| self.token(token))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Token<'_>> + '_ {
+        self.tokens.iter().map(|&token| self.token(token))
+    }
+
+    fn token(&self, (offset, len, kind, line): (usize, usize, TokenKind, usize)) -> Token<'_> {
+        Token {
+            origin: &self.source[offset..offset + len],
+            offset,
+            kind,
+            whole: &self.source,
+            line,
+            tab_width: 1,
+            merged: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{error::LexError, TokenKind};
+
+    use super::TokenStream;
+
+    #[test]
+    fn it_keeps_the_tokens_after_the_input_is_gone() {
+        let stream = {
+            let input = String::from("task foo {}");
+            TokenStream::lex(input.as_str()).unwrap()
+        };
+        let tokens = stream
+            .iter()
+            .map(|t| (t.kind, t.origin, t.offset))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                (TokenKind::Task, "task", 0),
+                (TokenKind::Ident, "foo", 5),
+                (TokenKind::LeftBrace, "{", 9),
+                (TokenKind::RightBrace, "}", 10),
+                (TokenKind::Eof, "", 11),
+            ],
+            tokens
+        );
+        assert_eq!(5, stream.len());
+        assert_eq!("task foo {}", stream.source());
+    }
+
+    #[test]
+    fn it_ties_the_tokens_to_the_owned_source() {
+        let stream = TokenStream::lex("task foo {\n  bar\n}").unwrap();
+        let bar = stream.get(3).unwrap();
+        assert_eq!("bar", bar.origin);
+        assert_eq!((2, 3), (bar.line(), bar.column()));
+        assert!(stream.get(6).is_none());
+    }
+
+    #[test]
+    fn it_fails_on_the_first_error() {
+        let res = TokenStream::lex("task ? ?");
+        let Err(LexError::UnexpectedChar(e)) = res else {
+            panic!("should have failed");
+        };
+        assert_eq!(5, e.err_span.offset());
+    }
+
+    fn is_static<T: 'static>(_: &T) {}
+
+    #[test]
+    fn it_is_static() {
+        is_static(&TokenStream::lex(String::from("task")).unwrap());
+    }
+}
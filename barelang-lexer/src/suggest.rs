@@ -0,0 +1,104 @@
+use alloc::{string::ToString, vec::Vec};
+
+use crate::{error::KeywordSuggestion, Token, TokenKind, DEFAULT_KEYWORDS};
+
+/// Flags the identifiers that are one typo away from one of the
+/// [`DEFAULT_KEYWORDS`], like `tsak` for `task`.
+///
+/// Keywords shorter than four chars are left out, since too many names are a
+/// typo away from them, like `foo` from `for`.
+pub fn suggest_keywords(tokens: &[Token]) -> Vec<KeywordSuggestion> {
+    tokens
+        .iter()
+        .filter(|token| token.kind == TokenKind::Ident)
+        .filter_map(|token| {
+            let (keyword, _) = DEFAULT_KEYWORDS.iter().find(|(keyword, _)| {
+                keyword.len() >= 4 && is_one_edit_apart(token.origin, keyword)
+            })?;
+            Some(KeywordSuggestion {
+                src: token.error_source(),
+                ident: token.origin.to_string(),
+                keyword,
+                err_span: token.span().into(),
+            })
+        })
+        .collect()
+}
+
+// whether a single insertion, deletion, substitution or swap of two adjacent
+// chars turns `a` into `b`
+fn is_one_edit_apart(a: &str, b: &str) -> bool {
+    let (a, b) = (a.chars().collect::<Vec<_>>(), b.chars().collect::<Vec<_>>());
+    let (shorter, longer) = if a.len() <= b.len() {
+        (&a, &b)
+    } else {
+        (&b, &a)
+    };
+    if longer.len() - shorter.len() > 1 {
+        return false;
+    }
+    let Some(i) = shorter.iter().zip(longer.iter()).position(|(a, b)| a != b) else {
+        // equal, or one extra char at the end
+        return shorter.len() != longer.len();
+    };
+    if shorter.len() != longer.len() {
+        return shorter[i..] == longer[i + 1..];
+    }
+    shorter[i + 1..] == longer[i + 1..]
+        || (i + 1 < shorter.len()
+            && shorter[i] == longer[i + 1]
+            && shorter[i + 1] == longer[i]
+            && shorter[i + 2..] == longer[i + 2..])
+}
+
+#[cfg(test)]
+mod test {
+    use crate::tokenize;
+
+    use super::{is_one_edit_apart, suggest_keywords};
+
+    #[test]
+    fn it_suggests_a_keyword_for_a_typo() {
+        let tokens = tokenize("tsak foo {}\ntas bar {}").unwrap();
+        let suggestions = suggest_keywords(&tokens);
+        let found = suggestions
+            .iter()
+            .map(|s| (s.ident.as_str(), s.keyword, s.err_span.offset()))
+            .collect::<Vec<_>>();
+        assert_eq!(vec![("tsak", "task", 0), ("tas", "task", 12)], found);
+    }
+
+    #[test]
+    fn it_does_not_suggest_for_far_off_identifiers() {
+        let tokens = tokenize("task banana { foo i x }").unwrap();
+        assert!(suggest_keywords(&tokens).is_empty());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn it_renders_the_suggestion_as_help() {
+        let tokens = tokenize("retrun").unwrap();
+        let [suggestion] = &suggest_keywords(&tokens)[..] else {
+            panic!("should have suggested `return`");
+        };
+        let help = miette::Diagnostic::help(suggestion).unwrap().to_string();
+        assert_eq!("did you mean `return`?", help);
+        assert_eq!(
+            Some(miette::Severity::Warning),
+            miette::Diagnostic::severity(suggestion)
+        );
+    }
+
+    #[test]
+    fn it_counts_single_edits() {
+        assert!(is_one_edit_apart("tsak", "task"));
+        assert!(is_one_edit_apart("tas", "task"));
+        assert!(is_one_edit_apart("tasks", "task"));
+        assert!(is_one_edit_apart("tusk", "task"));
+        assert!(is_one_edit_apart("fór", "for"));
+        assert!(!is_one_edit_apart("task", "task"));
+        assert!(!is_one_edit_apart("ta", "task"));
+        assert!(!is_one_edit_apart("stak", "task"));
+        assert!(!is_one_edit_apart("banana", "task"));
+    }
+}
@@ -0,0 +1,54 @@
+use alloc::vec::Vec;
+
+use crate::{error::LexError, Token};
+
+/// Collects tokens up to the first error, keeping both.
+///
+/// Unlike collecting into a `Result<Vec<Token>, LexError>`, the tokens before
+/// the error are not thrown away.
+#[derive(Debug, Default)]
+pub struct TokenVec<'de> {
+    pub tokens: Vec<Token<'de>>,
+    pub error: Option<LexError>,
+}
+
+impl<'de> FromIterator<Result<Token<'de>, LexError>> for TokenVec<'de> {
+    fn from_iter<I: IntoIterator<Item = Result<Token<'de>, LexError>>>(iter: I) -> Self {
+        let mut collected = Self::default();
+        for token in iter {
+            match token {
+                Ok(token) => collected.tokens.push(token),
+                Err(e) => {
+                    collected.error = Some(e);
+                    break;
+                }
+            }
+        }
+        collected
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{error::LexError, Lexer, TokenKind};
+
+    use super::TokenVec;
+
+    #[test]
+    fn it_keeps_the_tokens_before_the_first_error() {
+        let collected: TokenVec = Lexer::new("task ? foo ?").collect();
+        let kinds = collected.tokens.iter().map(|t| t.kind).collect::<Vec<_>>();
+        assert_eq!(vec![TokenKind::Task], kinds);
+        let Some(LexError::UnexpectedChar(e)) = collected.error else {
+            panic!("should have failed");
+        };
+        assert_eq!(5, e.err_span.offset());
+    }
+
+    #[test]
+    fn it_collects_every_token_without_an_error() {
+        let collected: TokenVec = Lexer::new("task foo {}").collect();
+        assert_eq!(5, collected.tokens.len());
+        assert!(collected.error.is_none());
+    }
+}
@@ -0,0 +1,16 @@
+use std::{path::Path, process::Command};
+
+#[test]
+fn it_builds_without_std() {
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let status = Command::new(env!("CARGO"))
+        .arg("build")
+        .arg("--offline")
+        .arg("--manifest-path")
+        .arg(manifest_dir.join("tests/no_std/Cargo.toml"))
+        .arg("--target-dir")
+        .arg(manifest_dir.join("../target/no_std"))
+        .status()
+        .expect("cargo should run");
+    assert!(status.success(), "the no_std crate should build");
+}
@@ -0,0 +1,19 @@
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use barelang_lexer::{Lexer, TokenKind};
+
+pub fn kinds(input: &str) -> Vec<TokenKind> {
+    Lexer::new(input).filter_map(|t| t.ok()).map(|t| t.kind).collect()
+}
+
+pub fn first_error(input: &str) -> Option<String> {
+    let (_, errors) = Lexer::new(input).lex_all();
+    Some(errors.first()?.to_string())
+}